@@ -1,6 +1,6 @@
 
-use std::time::Instant;
-
+use ftth_dhcp::identity::ClientIdentity;
+use ftth_dhcp::lease::{Dhcp4Lease, Dhcp6Lease, LeaseStore};
 use ftth_dhcp::{ipv4, ipv6};
 use ftth_rtnl::RtnlClient;
 
@@ -37,18 +37,22 @@ fn main() -> std::io::Result<()> {
         return Err(std::io::Error::other("IPv6 LL address not found"));
     };
 
+    let lease_store = LeaseStore::new(&std::env::temp_dir(), ifname);
+
     println!("Testing DHCPv4...");
     let e = {
         let v4_client = ipv4::Dhcp4Client::new(mac_addr.inner, ifname)?;
-        v4_client.discover()?;
-        let res = v4_client.recv(ipv4::MessageType::Offer)?;
-        println!("DISCOVER response (OFFER): {:?}", res);
-        if res.client_addr.is_none() || res.server_addr.is_none() {
-            Err(std::io::Error::other("No server/client address found"))?;
+        let previous = lease_store.load_dhcp4()?;
+        match v4_client.obtain_lease(previous.as_ref())? {
+            ipv4::Dhcp4Outcome::Ipv6Only(wait) => {
+                println!("IPv6-only preferred; not configuring IPv4 for {:?}", wait);
+                lease_store.remove_dhcp4()?;
+            },
+            ipv4::Dhcp4Outcome::Lease(res) => {
+                lease_store.store_dhcp4(&Dhcp4Lease::from_response(&res)?)?;
+                println!("IPv4 lease:\n{:?}", res);
+            },
         }
-        v4_client.request(ipv4::Dhcp4RequestType::Select, res.client_addr.unwrap(), res.server_addr.unwrap())?;
-        let res = v4_client.recv(ipv4::MessageType::Ack)?;
-        println!("IPv4 lease:\n{:?}", res);
         Ok::<(), std::io::Error>(())
     };
     if let Err(e) = e {
@@ -57,17 +61,14 @@ fn main() -> std::io::Result<()> {
 
     println!("Testing DHCPv6...");
     let e = {
-        let init = Instant::now();
-        let ia_id: u32 = rand::random();
-        let v6_client = ipv6::Dhcp6Client::new(ll_addr, mac_addr.inner, ifname)?;
-        v6_client.solicit_pd(init.elapsed(), ia_id)?;
-        let res = v6_client.recv(ipv6::MessageType::Advertise)?;
-        println!("SOLICIT response (ADVERTISE): {:?}", res);
-        if res.pd.is_none() {
-            Err(std::io::Error::other("PD prefix not received"))?;
-        }
-        v6_client.request_pd(init.elapsed(), ia_id, res.server_id, res.pd.unwrap())?;
-        let res = v6_client.recv(ipv6::MessageType::Reply)?;
+        let identity_path = std::env::temp_dir().join(format!("{}.identity", ifname));
+        let identity = ClientIdentity::load_or_create(&identity_path, if_id, mac_addr.inner)?;
+        let ia_id = identity.iaid;
+        let config = ipv6::Dhcp6ClientConfig::default().with_duid(identity.duid);
+        let v6_client = ipv6::Dhcp6Client::with_config(ll_addr, mac_addr.inner, ifname, config)?;
+        let previous = lease_store.load_dhcp6()?;
+        let res = v6_client.obtain_pd(ia_id, previous.as_ref())?;
+        lease_store.store_dhcp6(&Dhcp6Lease::from_response(&res, ia_id)?)?;
         println!("IPv6 lease:\n{:?}", res);
         Ok::<(), std::io::Error>(())
     };
@@ -0,0 +1,369 @@
+
+//! Time sources and retransmission timing.
+
+use std::fmt::Debug;
+use std::io::{ErrorKind, Read};
+use std::ops::{Add, Sub};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+/// A point on `CLOCK_BOOTTIME`: monotonic like [`std::time::Instant`], but
+/// it keeps counting while the system is suspended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BootInstant(Duration);
+
+impl BootInstant {
+    pub fn from_since_boot(d: Duration) -> Self {
+        Self(d)
+    }
+
+    pub fn since_boot(&self) -> Duration {
+        self.0
+    }
+
+    pub fn saturating_duration_since(&self, earlier: BootInstant) -> Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+}
+
+impl Add<Duration> for BootInstant {
+    type Output = BootInstant;
+
+    fn add(self, rhs: Duration) -> BootInstant {
+        BootInstant(self.0 + rhs)
+    }
+}
+
+impl Sub<BootInstant> for BootInstant {
+    type Output = Duration;
+
+    /// Saturates at zero.
+    fn sub(self, rhs: BootInstant) -> Duration {
+        self.saturating_duration_since(rhs)
+    }
+}
+
+/// Source of time for every timer in the crate.
+pub trait Clock: Debug + Send + Sync {
+    /// Boot time, used for all deadlines.
+    fn now(&self) -> BootInstant;
+
+    /// Wall-clock time, used only for persisted timestamps.
+    fn wall(&self) -> SystemTime;
+
+    fn sleep(&self, d: Duration);
+}
+
+/// The operating system's clocks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> BootInstant {
+        let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        // SAFETY: ts is a valid timespec; CLOCK_BOOTTIME exists since Linux 2.6.39.
+        let ret = unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut ts) };
+        assert_eq!(ret, 0, "clock_gettime(CLOCK_BOOTTIME) failed");
+        BootInstant(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+    }
+
+    fn wall(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, d: Duration) {
+        std::thread::sleep(d)
+    }
+}
+
+/// A clock that only moves when told to; `sleep` returns immediately
+/// after advancing it.
+#[derive(Debug)]
+pub struct FakeClock {
+    wall_start: SystemTime,
+    state: Mutex<FakeClockState>,
+}
+
+#[derive(Debug, Default)]
+struct FakeClockState {
+    elapsed: Duration,
+    wall_forward: Duration,
+    wall_back: Duration,
+}
+
+impl Default for FakeClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakeClock {
+    pub fn new() -> Self {
+        Self {
+            wall_start: SystemTime::now(),
+            state: Mutex::new(FakeClockState::default()),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, FakeClockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Lets time pass on both clocks, e.g. while suspended.
+    pub fn advance(&self, d: Duration) {
+        self.state().elapsed += d;
+    }
+
+    /// Steps only the wall clock forward, as an NTP sync would.
+    pub fn step_wall_forward(&self, d: Duration) {
+        self.state().wall_forward += d;
+    }
+
+    pub fn step_wall_back(&self, d: Duration) {
+        self.state().wall_back += d;
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> BootInstant {
+        BootInstant(self.state().elapsed)
+    }
+
+    fn wall(&self) -> SystemTime {
+        let state = self.state();
+        self.wall_start + state.elapsed + state.wall_forward - state.wall_back
+    }
+
+    fn sleep(&self, d: Duration) {
+        self.advance(d)
+    }
+}
+
+/// A discontinuity noticed by [`JumpDetector`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockJump {
+    /// More boot time passed than expected, typically while suspended.
+    Suspended(Duration),
+    /// The wall clock was stepped relative to boot time.
+    WallStepped { forward: bool, by: Duration },
+}
+
+/// Compares successive readings of a [`Clock`] to notice suspends and wall
+/// clock steps larger than a threshold.
+#[derive(Debug, Clone)]
+pub struct JumpDetector {
+    threshold: Duration,
+    boot: BootInstant,
+    wall: SystemTime,
+}
+
+impl JumpDetector {
+    pub const DEFAULT_THRESHOLD: Duration = Duration::from_secs(10);
+
+    pub fn new(clock: &dyn Clock, threshold: Duration) -> Self {
+        Self {
+            threshold,
+            boot: clock.now(),
+            wall: clock.wall(),
+        }
+    }
+
+    /// Takes a new reading, `expected` being how long the caller meant to
+    /// wait since the last one.
+    pub fn check(&mut self, clock: &dyn Clock, expected: Duration) -> Option<ClockJump> {
+        let boot = clock.now();
+        let wall = clock.wall();
+        let boot_delta = boot - self.boot;
+        let (wall_forward, wall_delta) = match wall.duration_since(self.wall) {
+            Ok(d) => (true, d),
+            Err(e) => (false, e.duration()),
+        };
+        self.boot = boot;
+        self.wall = wall;
+        if boot_delta > expected + self.threshold {
+            return Some(ClockJump::Suspended(boot_delta - expected));
+        }
+        if !wall_forward {
+            let by = wall_delta + boot_delta;
+            return (by > self.threshold).then_some(ClockJump::WallStepped { forward: false, by });
+        }
+        if wall_delta > boot_delta + self.threshold {
+            return Some(ClockJump::WallStepped { forward: true, by: wall_delta - boot_delta });
+        }
+        if boot_delta > wall_delta + self.threshold {
+            return Some(ClockJump::WallStepped { forward: false, by: boot_delta - wall_delta });
+        }
+        None
+    }
+}
+
+/// Retransmission parameters (RFC 8415 section 15). `None` means no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetransParams {
+    /// Initial retransmission time.
+    pub irt: Duration,
+    /// Maximum retransmission time.
+    pub mrt: Option<Duration>,
+    /// Maximum number of transmissions.
+    pub mrc: Option<u32>,
+    /// Maximum duration of the whole exchange.
+    pub mrd: Option<Duration>,
+    /// Whether the first timeout must exceed IRT, as for Solicit.
+    pub first_rt_above_irt: bool,
+}
+
+impl RetransParams {
+    pub const SOLICIT: Self = Self {
+        first_rt_above_irt: true,
+        ..Self::new(1, Some(3600), None, None)
+    };
+    pub const REQUEST: Self = Self::new(1, Some(30), Some(10), None);
+    pub const CONFIRM: Self = Self::new(1, Some(4), None, Some(10));
+    pub const RENEW: Self = Self::new(10, Some(600), None, None);
+    pub const REBIND: Self = Self::new(10, Some(600), None, None);
+    pub const RELEASE: Self = Self::new(1, None, Some(4), None);
+    pub const INFORMATION_REQUEST: Self = Self::new(1, Some(3600), None, None);
+    /// DHCPv4 backoff from 4 up to 64 seconds (RFC 2131 section 4.1).
+    pub const DHCP4: Self = Self::new(4, Some(64), Some(5), None);
+
+    const fn new(irt: u64, mrt: Option<u64>, mrc: Option<u32>, mrd: Option<u64>) -> Self {
+        const fn secs(s: Option<u64>) -> Option<Duration> {
+            match s {
+                Some(s) => Some(Duration::from_secs(s)),
+                None => None,
+            }
+        }
+        Self {
+            irt: Duration::from_secs(irt),
+            mrt: secs(mrt),
+            mrc,
+            mrd: secs(mrd),
+            first_rt_above_irt: false,
+        }
+    }
+
+    pub fn with_mrd(mut self, mrd: Duration) -> Self {
+        self.mrd = Some(mrd);
+        self
+    }
+}
+
+/// Computes the timeout before each transmission of one exchange.
+#[derive(Debug, Clone)]
+pub struct Retransmission {
+    params: RetransParams,
+    start: BootInstant,
+    count: u32,
+    rt: Duration,
+}
+
+impl Retransmission {
+    pub fn new(params: RetransParams, start: BootInstant) -> Self {
+        Self {
+            params,
+            start,
+            count: 0,
+            rt: Duration::ZERO,
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// How long to wait for a reply to the next transmission, or `None` if
+    /// the exchange has failed. `rand` is the randomization factor in
+    /// [-0.1, 0.1].
+    pub fn next_timeout(&mut self, now: BootInstant, rand: f64) -> Option<Duration> {
+        let p = &self.params;
+        if p.mrc.is_some_and(|mrc| self.count >= mrc) {
+            return None;
+        }
+        let left = match p.mrd {
+            Some(mrd) => {
+                let left = mrd.checked_sub(now.saturating_duration_since(self.start))?;
+                if left.is_zero() {
+                    return None;
+                }
+                Some(left)
+            },
+            None => None,
+        };
+        let rand = rand.clamp(-0.1, 0.1);
+        let mut rt = if self.count == 0 {
+            let rand = if p.first_rt_above_irt { rand.abs() } else { rand };
+            p.irt.mul_f64(1.0 + rand)
+        } else {
+            self.rt.mul_f64(2.0 + rand)
+        };
+        if let Some(mrt) = p.mrt.filter(|mrt| rt > *mrt) {
+            rt = mrt.mul_f64(1.0 + rand);
+        }
+        if let Some(left) = left {
+            rt = rt.min(left);
+        }
+        self.count += 1;
+        self.rt = rt;
+        Some(rt)
+    }
+}
+
+/// A randomization factor in [-0.1, 0.1] for [`Retransmission::next_timeout`].
+pub fn random_factor() -> f64 {
+    (next_random() >> 11) as f64 / (1u64 << 53) as f64 * 0.2 - 0.1
+}
+
+/// SplitMix64, seeded from the kernel on first use; good enough to
+/// spread out retransmissions, and cheap to call for each one.
+fn next_random() -> u64 {
+    const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+    static STATE: OnceLock<AtomicU64> = OnceLock::new();
+    let state = STATE.get_or_init(|| {
+        let mut buf = [0u8; 8];
+        let seed = match std::fs::File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut buf)) {
+            Ok(()) => u64::from_ne_bytes(buf),
+            Err(_) => SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64),
+        };
+        AtomicU64::new(seed)
+    });
+    let mut z = state.fetch_add(GAMMA, Ordering::Relaxed).wrapping_add(GAMMA);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Runs one exchange: transmits with `send`, which gets the time elapsed
+/// since the first transmission, and waits for a reply with `recv`, which
+/// gets the time left on `clock` until the current timeout and should wait
+/// that long on the same clock (see [`crate::transport::Transport::recv`]). `recv` failing with
+/// `InvalidData` means the packet was not a reply to us; it is dropped and
+/// `recv` is called again. Retransmits whenever the timeout passes.
+pub fn retransmit<T, S, R>(clock: &dyn Clock, params: RetransParams, mut send: S, mut recv: R) -> std::io::Result<T>
+where
+    S: FnMut(Duration) -> std::io::Result<()>,
+    R: FnMut(Duration) -> std::io::Result<T>,
+{
+    let start = clock.now();
+    let mut retrans = Retransmission::new(params, start);
+    loop {
+        let now = clock.now();
+        let Some(timeout) = retrans.next_timeout(now, random_factor()) else {
+            return Err(std::io::Error::new(ErrorKind::TimedOut, "No reply from server"));
+        };
+        send(now.saturating_duration_since(start))?;
+        let deadline = now + timeout;
+        loop {
+            let left = deadline - clock.now();
+            if left.is_zero() {
+                break;
+            }
+            match recv(left) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => break,
+                Err(e) if e.kind() == ErrorKind::InvalidData => log::debug!("Dropping packet: {}", e),
+                res => return res,
+            }
+        }
+        log::debug!("No reply within {:?}, retransmitting", timeout);
+    }
+}
@@ -0,0 +1,145 @@
+
+//! DHCPv4 over DHCPv6 (RFC 7341): DHCPv4 messages carried in
+//! DHCPV4-QUERY and DHCPV4-RESPONSE messages.
+
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use dhcproto::v6::{DhcpOption, MessageType, OptionCode, UnknownOption};
+use dhcproto::{Decodable, Decoder, Encodable, Encoder};
+
+use crate::clock::Clock;
+use crate::persist::invalid;
+use crate::transport::Transport;
+
+pub const OPTION_DHCPV4_MSG: u16 = 87;
+pub const OPTION_DHCP4_O_DHCP6_SERVER: u16 = 88;
+
+/// Set in DHCPV4-QUERY when the DHCPv4 message would have been unicast.
+pub const FLAG_UNICAST: u32 = 0x80_0000;
+
+/// Wraps an encoded DHCPv4 message in a DHCPV4-QUERY.
+pub fn encode_query(dhcp4: &[u8], unicast: bool) -> std::io::Result<Vec<u8>> {
+    let flags = if unicast { FLAG_UNICAST } else { 0 };
+    let mut msg = dhcproto::v6::Message::new(MessageType::DHCPv4Query);
+    // the flags take the place of the transaction ID
+    msg.set_xid_num(flags);
+    msg.opts_mut().insert(DhcpOption::Unknown(UnknownOption::new(OptionCode::Dhcpv4Msg, dhcp4.to_vec())));
+    let mut buf = Vec::new();
+    msg.encode(&mut Encoder::new(&mut buf)).map_err(|_| invalid("DHCPv6 encoding failed"))?;
+    Ok(buf)
+}
+
+/// Extracts the DHCPv4 message from a DHCPV4-RESPONSE.
+pub fn decode_response(packet: &[u8]) -> std::io::Result<Vec<u8>> {
+    let msg = dhcproto::v6::Message::decode(&mut Decoder::new(packet))
+        .map_err(|_| invalid("DHCPv6 decoding failed"))?;
+    if msg.msg_type() != MessageType::DHCPv4Response {
+        return Err(invalid("Not a DHCPV4-RESPONSE"));
+    }
+    match msg.opts().get(OptionCode::Dhcpv4Msg) {
+        Some(DhcpOption::Unknown(opt)) => Ok(opt.data().to_vec()),
+        _ => Err(invalid("No DHCPv4 message in DHCPV4-RESPONSE")),
+    }
+}
+
+/// Decodes the DHCP 4o6 Server Address option. An empty list means the
+/// client should use the All_DHCP_Relay_Agents_and_Servers address.
+pub fn decode_servers(data: &[u8]) -> std::io::Result<Vec<Ipv6Addr>> {
+    if !data.len().is_multiple_of(16) {
+        return Err(invalid("Invalid DHCP 4o6 Server Address option"));
+    }
+    Ok(data.chunks_exact(16).map(|c| Ipv6Addr::from(<[u8; 16]>::try_from(c).unwrap())).collect())
+}
+
+/// The DHCPv6 client socket, shared by a [`crate::ipv6::Dhcp6Client`] and a
+/// DHCPv4 client running over it: DHCPV4-RESPONSE messages are for the
+/// DHCPv4 client and everything else for the DHCPv6 one.
+#[derive(Debug)]
+struct Demux {
+    inner: Arc<dyn Transport>,
+    /// Held by whichever side is reading from `inner`.
+    reader: Mutex<()>,
+    /// Packets read for the DHCPv6 side and the DHCPv4 side.
+    queues: Mutex<[VecDeque<Vec<u8>>; 2]>,
+}
+
+impl Demux {
+    /// Longest a side reads from the socket at once, so that the other
+    /// side soon gets its turn.
+    const SLICE: Duration = Duration::from_millis(100);
+    /// Packets kept for a side that is not reading.
+    const QUEUE_LEN: usize = 16;
+
+    fn side(packet: &[u8]) -> usize {
+        match packet.first() {
+            Some(&t) if t == u8::from(MessageType::DHCPv4Response) => 1,
+            _ => 0,
+        }
+    }
+
+    fn recv(&self, side: usize, clock: &dyn Clock, timeout: Duration) -> std::io::Result<Vec<u8>> {
+        let deadline = clock.now() + timeout;
+        loop {
+            if let Some(packet) = self.queues.lock().unwrap_or_else(|e| e.into_inner())[side].pop_front() {
+                return Ok(packet);
+            }
+            let left = deadline.saturating_duration_since(clock.now());
+            if left.is_zero() {
+                return Err(std::io::Error::new(ErrorKind::TimedOut, "No packet received"));
+            }
+            let _reader = self.reader.lock().unwrap_or_else(|e| e.into_inner());
+            // the other side may have read ours while we waited
+            if let Some(packet) = self.queues.lock().unwrap_or_else(|e| e.into_inner())[side].pop_front() {
+                return Ok(packet);
+            }
+            let packet = match self.inner.recv(clock, left.min(Self::SLICE)) {
+                Ok(packet) => packet,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+                Err(e) => return Err(e),
+            };
+            let other = Self::side(&packet);
+            if other == side {
+                return Ok(packet);
+            }
+            let mut queues = self.queues.lock().unwrap_or_else(|e| e.into_inner());
+            if queues[other].len() == Self::QUEUE_LEN {
+                queues[other].pop_front();
+            }
+            queues[other].push_back(packet);
+        }
+    }
+}
+
+/// One client's view of a shared DHCPv6 socket.
+#[derive(Debug)]
+pub(crate) struct SharedTransport {
+    demux: Arc<Demux>,
+    side: usize,
+}
+
+impl SharedTransport {
+    /// Shares `inner` between a DHCPv6 client, which gets the first
+    /// transport returned, and a DHCPv4 client over DHCPv6.
+    pub(crate) fn split(inner: Arc<dyn Transport>) -> (Self, Self) {
+        let demux = Arc::new(Demux {
+            inner,
+            reader: Mutex::new(()),
+            queues: Mutex::new([VecDeque::new(), VecDeque::new()]),
+        });
+        (Self { demux: demux.clone(), side: 0 }, Self { demux, side: 1 })
+    }
+}
+
+impl Transport for SharedTransport {
+    fn send_to(&self, packet: &[u8], dest: SocketAddr) -> std::io::Result<()> {
+        self.demux.inner.send_to(packet, dest)
+    }
+
+    fn recv(&self, clock: &dyn Clock, timeout: Duration) -> std::io::Result<Vec<u8>> {
+        self.demux.recv(self.side, clock, timeout)
+    }
+}
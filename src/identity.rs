@@ -0,0 +1,228 @@
+
+//! Client identity: DHCPv6 DUIDs, IAIDs and their persistence.
+
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::persist::{from_hex, parse_kv, to_hex, write_atomic};
+
+pub const HW_TYPE_ETHERNET: u16 = 1;
+
+/// Seconds between the Unix epoch and the DUID-LLT epoch (2000-01-01 UTC).
+const DUID_TIME_EPOCH: u64 = 946_684_800;
+
+/// DHCP Unique Identifier (RFC 8415 section 11).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Duid {
+    /// DUID-LLT: link-layer address plus time.
+    Llt {
+        hw_type: u16,
+        time: u32,
+        link_layer_addr: Vec<u8>,
+    },
+    /// DUID-EN: assigned by vendor based on enterprise number.
+    En {
+        enterprise: u32,
+        identifier: Vec<u8>,
+    },
+    /// DUID-LL: link-layer address.
+    Ll {
+        hw_type: u16,
+        link_layer_addr: Vec<u8>,
+    },
+    /// DUID-UUID (RFC 6355).
+    Uuid([u8; 16]),
+    /// Any other DUID type, including its 2-Byte type code.
+    Other(Vec<u8>),
+}
+
+impl Duid {
+    pub fn ll_from_mac(mac: [u8; 6]) -> Self {
+        Self::Ll {
+            hw_type: HW_TYPE_ETHERNET,
+            link_layer_addr: mac.to_vec(),
+        }
+    }
+
+    /// DUID-LLT stamped with `now`.
+    pub fn llt_from_mac(mac: [u8; 6], now: SystemTime) -> Self {
+        let secs = now.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_secs();
+        Self::Llt {
+            hw_type: HW_TYPE_ETHERNET,
+            time: secs.saturating_sub(DUID_TIME_EPOCH) as u32,
+            link_layer_addr: mac.to_vec(),
+        }
+    }
+
+    pub fn en(enterprise: u32, identifier: &[u8]) -> Self {
+        Self::En {
+            enterprise,
+            identifier: identifier.to_vec(),
+        }
+    }
+
+    /// DUID-UUID from a random (version 4) UUID.
+    pub fn random_uuid() -> std::io::Result<Self> {
+        let mut uuid = [0u8; 16];
+        std::fs::File::open("/dev/urandom")?.read_exact(&mut uuid)?;
+        uuid[6] = (uuid[6] & 0x0f) | 0x40;
+        uuid[8] = (uuid[8] & 0x3f) | 0x80;
+        Ok(Self::Uuid(uuid))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut duid = Vec::new();
+        match self {
+            Self::Llt { hw_type, time, link_layer_addr } => {
+                duid.extend_from_slice(&1u16.to_be_bytes());
+                duid.extend_from_slice(&hw_type.to_be_bytes());
+                duid.extend_from_slice(&time.to_be_bytes());
+                duid.extend_from_slice(link_layer_addr);
+            },
+            Self::En { enterprise, identifier } => {
+                duid.extend_from_slice(&2u16.to_be_bytes());
+                duid.extend_from_slice(&enterprise.to_be_bytes());
+                duid.extend_from_slice(identifier);
+            },
+            Self::Ll { hw_type, link_layer_addr } => {
+                duid.extend_from_slice(&3u16.to_be_bytes());
+                duid.extend_from_slice(&hw_type.to_be_bytes());
+                duid.extend_from_slice(link_layer_addr);
+            },
+            Self::Uuid(uuid) => {
+                duid.extend_from_slice(&4u16.to_be_bytes());
+                duid.extend_from_slice(uuid);
+            },
+            Self::Other(data) => {
+                duid.extend_from_slice(data);
+            },
+        }
+        duid
+    }
+
+    pub fn from_bytes(data: &[u8]) -> std::io::Result<Self> {
+        let invalid = || std::io::Error::new(ErrorKind::InvalidData, "Invalid DUID");
+        // RFC 8415 11.1: at most 128 octets, not counting the type code
+        if data.len() < 3 || data.len() > 130 {
+            return Err(invalid());
+        }
+        let duid_type = u16::from_be_bytes([data[0], data[1]]);
+        let body = &data[2..];
+        let duid = match duid_type {
+            1 if body.len() > 6 => Self::Llt {
+                hw_type: u16::from_be_bytes([body[0], body[1]]),
+                time: u32::from_be_bytes(body[2..6].try_into().unwrap()),
+                link_layer_addr: body[6..].to_vec(),
+            },
+            2 if body.len() > 4 => Self::En {
+                enterprise: u32::from_be_bytes(body[0..4].try_into().unwrap()),
+                identifier: body[4..].to_vec(),
+            },
+            3 if body.len() > 2 => Self::Ll {
+                hw_type: u16::from_be_bytes([body[0], body[1]]),
+                link_layer_addr: body[2..].to_vec(),
+            },
+            4 => Self::Uuid(body.try_into().map_err(|_| invalid())?),
+            1..=4 => return Err(invalid()),
+            _ => Self::Other(data.to_vec()),
+        };
+        Ok(duid)
+    }
+
+    /// Reads the DUID stored at `path`.
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let (_, hex) = parse_kv(&contents).into_iter()
+            .find(|(k, _)| *k == "duid")
+            .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, "No DUID in file"))?;
+        Self::from_bytes(&from_hex(hex)?)
+    }
+
+    /// Atomically writes the DUID to `path`.
+    pub fn store(&self, path: &Path) -> std::io::Result<()> {
+        let contents = format!("duid={}\n", to_hex(&self.to_bytes()));
+        write_atomic(path, contents.as_bytes())
+    }
+
+    /// Loads the DUID stored at `path`, or creates one with `generate` and
+    /// stores it, so the identity survives reboots and NIC replacement.
+    pub fn load_or_create<F>(path: &Path, generate: F) -> std::io::Result<Self>
+    where
+        F: FnOnce() -> std::io::Result<Self>,
+    {
+        match Self::load(path) {
+            Ok(duid) => Ok(duid),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let duid = generate()?;
+                duid.store(path)?;
+                log::info!("Generated new DUID: {}", to_hex(&duid.to_bytes()));
+                Ok(duid)
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Derives an IAID that stays the same across restarts for a given
+/// interface (RFC 8415 section 12), from its index and MAC address.
+pub fn iaid_from_interface(if_index: u32, mac: [u8; 6]) -> u32 {
+    // FNV-1a
+    let mut hash: u32 = 0x811c_9dc5;
+    for b in mac.iter().chain(if_index.to_be_bytes().iter()) {
+        hash ^= *b as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+/// DUID and IAID kept together, so both survive reboots and hardware swaps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+    pub duid: Duid,
+    pub iaid: u32,
+}
+
+impl ClientIdentity {
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let mut duid = None;
+        let mut iaid = None;
+        for (key, value) in parse_kv(&contents) {
+            match key {
+                "duid" => duid = Some(Duid::from_bytes(&from_hex(value)?)?),
+                "iaid" => iaid = Some(u32::from_str_radix(value, 16).map_err(|_| std::io::Error::new(ErrorKind::InvalidData, "Invalid IAID"))?),
+                _ => {},
+            }
+        }
+        match (duid, iaid) {
+            (Some(duid), Some(iaid)) => Ok(Self { duid, iaid }),
+            _ => Err(std::io::Error::new(ErrorKind::InvalidData, "Incomplete client identity")),
+        }
+    }
+
+    pub fn store(&self, path: &Path) -> std::io::Result<()> {
+        let contents = format!("duid={}\niaid={:08x}\n", to_hex(&self.duid.to_bytes()), self.iaid);
+        write_atomic(path, contents.as_bytes())
+    }
+
+    /// Loads the identity stored at `path`, or creates a DUID-LLT and an
+    /// interface-derived IAID and stores them. A file holding only a DUID
+    /// (see [`Duid::store`]) keeps that DUID. Any other unreadable file is
+    /// an error and is left alone, so the identity is never silently lost.
+    pub fn load_or_create(path: &Path, if_index: u32, mac: [u8; 6]) -> std::io::Result<Self> {
+        let duid = match Self::load(path) {
+            Ok(identity) => return Ok(identity),
+            Err(e) if e.kind() == ErrorKind::NotFound => Duid::llt_from_mac(mac, SystemTime::now()),
+            Err(e) if e.kind() == ErrorKind::InvalidData => Duid::load(path).map_err(|_| e)?,
+            Err(e) => return Err(e),
+        };
+        let identity = Self {
+            duid,
+            iaid: iaid_from_interface(if_index, mac),
+        };
+        identity.store(path)?;
+        log::info!("Stored client identity: DUID {}, IAID {:08x}", to_hex(&identity.duid.to_bytes()), identity.iaid);
+        Ok(identity)
+    }
+}
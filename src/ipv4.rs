@@ -1,22 +1,177 @@
 
 use std::io::ErrorKind;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+use dhcproto::v4::fqdn::{ClientFQDN, FqdnFlags};
 use dhcproto::v4::{DhcpOption, Flags, HType, Message, Opcode, OptionCode, UnknownOption, CLIENT_PORT};
 use dhcproto::{Decodable, Decoder, Encodable, Encoder};
+use ipnet::Ipv4Net;
 use socket2::{Socket, Domain, Type};
 
+use crate::auth::{self, AuthKey, AuthValue};
+use crate::clock::{self, Clock, RetransParams, SystemClock};
+use crate::dhcp4o6;
+use crate::identity::Duid;
+use crate::ipv6::Dhcp6Client;
+use crate::lease::{Dhcp4Lease, LeaseTimes, ReceivedAt};
+use crate::md5;
+use crate::ntt::{self, NttVendorInfo};
+use crate::options::{parse_dhcp4_options, CustomOptions, OptionDecoders, RawOption};
+use crate::profile::{NttNgnProfile, ProvisioningProfile};
+use crate::transport::{Transport, UdpTransport};
+
 pub use dhcproto::v4::MessageType;
 
 #[derive(Debug)]
 pub struct Dhcp4Client {
-    socket: std::net::UdpSocket,
+    transport: Arc<dyn Transport>,
     local_if_mac: [u8; 6],
+    config: Dhcp4ClientConfig,
+    state: Mutex<Dhcp4State>,
+    /// DHCP 4o6 servers when running over DHCPv6 (RFC 7341); empty for
+    /// All_DHCP_Relay_Agents_and_Servers.
+    dhcp4o6_servers: Option<Vec<Ipv6Addr>>,
+}
+
+/// What the client remembers between messages.
+#[derive(Debug, Default)]
+struct Dhcp4State {
+    /// Server address and the forcerenew nonce (RFC 6704) it sent.
+    forcerenew_nonce: Option<(Ipv4Addr, AuthKey)>,
+}
+
+/// Options requested from and sent to the server.
+///
+/// The request lists start out as the profile's; the default profile is
+/// [`NttNgnProfile`], matching what the client has always sent.
+#[derive(Debug, Clone)]
+pub struct Dhcp4ClientConfig {
+    pub profile: Arc<dyn ProvisioningProfile>,
+    /// Client identifier (option 61); when unset the profile decides.
+    pub client_id: Option<Dhcp4ClientId>,
+    /// Parameter Request List sent in DHCPDISCOVER.
+    pub discover_params: Vec<OptionCode>,
+    /// Parameter Request List sent in DHCPREQUEST.
+    pub request_params: Vec<OptionCode>,
+    /// Decoders for options the crate does not interpret itself.
+    pub decoders: OptionDecoders,
+    /// Options appended verbatim to DHCPDISCOVER and DHCPREQUEST.
+    pub extra_options: Vec<DhcpOption>,
+    /// Time source for retransmission and lease timers.
+    pub clock: Arc<dyn Clock>,
+    /// Sends Rapid Commit (RFC 4039) in DHCPDISCOVER, so that a server
+    /// may reply with a DHCPACK right away.
+    pub rapid_commit: bool,
+    /// Sends Forcerenew Nonce Capable (RFC 6704), so that a server may
+    /// authenticate DHCPFORCERENEW with a nonce.
+    pub forcerenew_nonce_capable: bool,
+    /// Requests IPv6-Only Preferred (RFC 8925), so that on IPv6-mostly
+    /// networks the client leaves DHCPv4 alone.
+    pub ipv6_only_preferred: bool,
+}
+
+impl Default for Dhcp4ClientConfig {
+    fn default() -> Self {
+        Self::new(Arc::new(NttNgnProfile))
+    }
+}
+
+impl Dhcp4ClientConfig {
+    pub fn new(profile: Arc<dyn ProvisioningProfile>) -> Self {
+        Self {
+            client_id: None,
+            discover_params: profile.dhcp4_discover_params(),
+            request_params: profile.dhcp4_request_params(),
+            decoders: OptionDecoders::new(),
+            extra_options: Vec::new(),
+            clock: Arc::new(SystemClock),
+            rapid_commit: false,
+            forcerenew_nonce_capable: false,
+            ipv6_only_preferred: false,
+            profile,
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_rapid_commit(mut self) -> Self {
+        self.rapid_commit = true;
+        self
+    }
+
+    pub fn with_forcerenew_nonce(mut self) -> Self {
+        self.forcerenew_nonce_capable = true;
+        self
+    }
+
+    pub fn with_ipv6_only_preferred(mut self) -> Self {
+        self.ipv6_only_preferred = true;
+        self.with_param(OptionCode::Unknown(OPTION_IPV6_ONLY_PREFERRED))
+    }
+
+    pub fn with_client_id(mut self, client_id: Dhcp4ClientId) -> Self {
+        self.client_id = Some(client_id);
+        self
+    }
+
+    /// Adds `code` to both Parameter Request Lists, if not already present.
+    pub fn with_param(mut self, code: OptionCode) -> Self {
+        for list in [&mut self.discover_params, &mut self.request_params] {
+            if !list.contains(&code) {
+                list.push(code);
+            }
+        }
+        self
+    }
+
+    /// Adds an option to send, replacing any earlier option with the same code.
+    pub fn with_option(mut self, opt: DhcpOption) -> Self {
+        let code = OptionCode::from(&opt);
+        self.extra_options.retain(|o| OptionCode::from(o) != code);
+        self.extra_options.push(opt);
+        self
+    }
+
+    /// Host Name (option 12).
+    pub fn with_hostname(self, hostname: &str) -> Self {
+        self.with_option(DhcpOption::Hostname(hostname.to_string()))
+    }
+
+    /// Vendor class identifier (option 60).
+    pub fn with_vendor_class_id(self, id: &[u8]) -> Self {
+        self.with_option(DhcpOption::ClassIdentifier(id.to_vec()))
+    }
+
+    /// User Class (option 77), encoded as RFC 3004 length-prefixed classes.
+    /// Fails if a class is empty or longer than 255 Bytes.
+    pub fn with_user_classes(self, classes: &[&[u8]]) -> std::io::Result<Self> {
+        let mut data = Vec::new();
+        for class in classes {
+            let len = u8::try_from(class.len()).ok().filter(|len| *len > 0)
+                .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "Invalid user class length"))?;
+            data.push(len);
+            data.extend_from_slice(class);
+        }
+        Ok(self.with_option(DhcpOption::UserClass(data)))
+    }
+
+    /// Client FQDN (option 81), asking the server to perform the A RR update.
+    pub fn with_client_fqdn(self, fqdn: &str) -> std::io::Result<Self> {
+        let name = dhcproto::Name::from_ascii(fqdn)
+            .map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "Invalid FQDN"))?;
+        let flags = FqdnFlags::default().set_s(true).set_e(true);
+        Ok(self.with_option(DhcpOption::ClientFQDN(ClientFQDN::new(flags, name))))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Dhcp4Response {
+    pub msg_type: MessageType,
     pub client_addr: Option<Ipv4Addr>,
     pub server_addr: Option<Ipv4Addr>,
     pub router_addrs: Vec<Ipv4Addr>,
@@ -28,7 +183,60 @@ pub struct Dhcp4Response {
     pub sip_domain_name: Option<String>,
     pub sip_main_number: Option<String>,
     pub sip_add_numbers: Vec<String>,
+    /// The whole NTT vendor option, of which the `sip_` fields above are part.
+    pub ntt_vendor_info: Option<NttVendorInfo>,
+    /// The MAC address echoed in the NTT vendor option, if it is not ours.
+    pub ntt_mac_mismatch: Option<[u8; 6]>,
     pub static_routes: Vec<Dhcp4Route>,
+    pub classful_routes: Vec<Dhcp4Route>,
+    pub ms_static_routes: Vec<Dhcp4Route>,
+    pub received_at: ReceivedAt,
+    /// Whether the reply carried Rapid Commit.
+    pub rapid_commit: bool,
+    /// Forcerenew nonce sent by the server, or the one it sent earlier.
+    pub forcerenew_nonce: Option<AuthKey>,
+    /// V6ONLY_WAIT from IPv6-Only Preferred, at least [`MIN_V6ONLY_WAIT`];
+    /// only set when requested.
+    pub v6only_wait: Option<Duration>,
+    /// Every option in the reply, as received.
+    pub raw_options: Vec<RawOption>,
+    /// Results of the decoders registered in [`Dhcp4ClientConfig::decoders`].
+    pub custom_options: CustomOptions,
+}
+
+/// Contents of the client identifier option (61).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dhcp4ClientId {
+    /// Hardware type 1 (Ethernet) followed by the interface MAC address.
+    HardwareAddr,
+    /// RFC 4361 node-specific identifier: type 255, IAID and DUID. Using
+    /// the DUID of the DHCPv6 client keeps the identity consistent across
+    /// both protocols.
+    NodeSpecific {
+        iaid: u32,
+        duid: Duid,
+    },
+    /// Sent as-is, including any type byte.
+    Custom(Vec<u8>),
+}
+
+impl Dhcp4ClientId {
+    pub fn to_bytes(&self, local_if_mac: [u8; 6]) -> Vec<u8> {
+        match self {
+            Self::HardwareAddr => {
+                let mut id = vec![HType::Eth.into()];
+                id.extend_from_slice(&local_if_mac);
+                id
+            },
+            Self::NodeSpecific { iaid, duid } => {
+                let mut id = vec![255];
+                id.extend_from_slice(&iaid.to_be_bytes());
+                id.extend_from_slice(&duid.to_bytes());
+                id
+            },
+            Self::Custom(id) => id.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -38,7 +246,193 @@ pub struct Dhcp4Route {
     pub gateway: Ipv4Addr,
 }
 
-#[derive(PartialEq, Eq)]
+/// Routes to install for a lease, after applying the RFC 3442 precedence rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dhcp4RouteSet {
+    /// The directly connected subnet, if both an address and a mask were offered.
+    pub connected: Option<Ipv4Net>,
+    pub routes: Vec<Dhcp4Route>,
+}
+
+impl Dhcp4Route {
+    /// The destination network; `None` if the prefix length exceeds 32.
+    pub fn prefix(&self) -> Option<Ipv4Net> {
+        Ipv4Net::new(self.prefix_addr, self.prefix_len).ok().map(|net| net.trunc())
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.prefix_len == 0
+    }
+
+    /// Option 33 destinations carry no mask; the classful mask is implied,
+    /// unless host bits are set, in which case it is a host route.
+    fn classful(dest: Ipv4Addr, gateway: Ipv4Addr) -> Self {
+        let first = dest.octets()[0];
+        let class_len = if first < 128 {
+            8
+        } else if first < 192 {
+            16
+        } else {
+            24
+        };
+        let class_mask = u32::MAX << (32 - class_len);
+        let prefix_len = if dest.to_bits() & !class_mask != 0 { 32 } else { class_len };
+        Self {
+            prefix_addr: dest,
+            prefix_len,
+            gateway,
+        }
+    }
+}
+
+/// Parses the RFC 3442 encoding, shared by options 121 and 249.
+pub fn parse_classless_routes(data: &[u8]) -> Vec<Dhcp4Route> {
+    let mut routes = Vec::new();
+    let mut pos = 0usize;
+    while pos < data.len() {
+        let prefix_len = data[pos];
+        if prefix_len > 32 {
+            break;
+        }
+        let significant = (prefix_len as usize).div_ceil(8);
+        let end = pos + 1 + significant + 4;
+        if end > data.len() {
+            break;
+        }
+        let mut dest = [0u8; 4];
+        dest[..significant].copy_from_slice(&data[pos + 1..pos + 1 + significant]);
+        let gateway: [u8; 4] = data[end - 4..end].try_into().unwrap();
+        routes.push(Dhcp4Route {
+            prefix_addr: dest.into(),
+            prefix_len,
+            gateway: gateway.into(),
+        });
+        pos = end;
+    }
+    routes
+}
+
+impl Dhcp4Response {
+    /// Lease deadlines counted from when this reply was received.
+    pub fn lease_times(&self) -> LeaseTimes {
+        LeaseTimes::new(self.received_at, self.addr_time, self.addr_time, self.renewal_time, self.rebind_time)
+    }
+
+    pub fn raw_option(&self, code: u8) -> Option<&[u8]> {
+        self.raw_options.iter().find(|o| o.code == code as u16).map(|o| o.data.as_slice())
+    }
+
+    /// Computes the routes implied by this lease.
+    ///
+    /// Per RFC 3442, when option 121 is present the Router option and the
+    /// classful option 33 are ignored. The Microsoft option 249 is used in
+    /// its place only when option 121 is absent.
+    pub fn effective_routes(&self) -> Dhcp4RouteSet {
+        let connected = match (self.client_addr, self.subnet_mask) {
+            (Some(addr), Some(mask)) => Ipv4Net::with_netmask(addr, mask).ok().map(|net| net.trunc()),
+            _ => None,
+        };
+
+        let classless = if !self.static_routes.is_empty() {
+            Some(&self.static_routes)
+        } else if !self.ms_static_routes.is_empty() {
+            Some(&self.ms_static_routes)
+        } else {
+            None
+        };
+
+        let mut routes = match classless {
+            Some(routes) => routes.clone(),
+            None => {
+                let mut routes = Vec::new();
+                if let Some(router) = self.router_addrs.first() {
+                    routes.push(Dhcp4Route {
+                        prefix_addr: Ipv4Addr::UNSPECIFIED,
+                        prefix_len: 0,
+                        gateway: *router,
+                    });
+                }
+                // A default route in option 33 is illegal (RFC 2132 5.8).
+                routes.extend(self.classful_routes.iter().filter(|r| !r.prefix_addr.is_unspecified()).cloned());
+                routes
+            },
+        };
+        routes.retain(|r| {
+            let valid = r.prefix().is_some();
+            if !valid {
+                log::warn!("Ignoring route with invalid prefix length {}", r.prefix_len);
+            }
+            valid
+        });
+
+        Dhcp4RouteSet {
+            connected,
+            routes,
+        }
+    }
+}
+
+fn decode_msg(packet: &[u8]) -> std::io::Result<(Message, Vec<RawOption>)> {
+    let msg = Message::decode(&mut Decoder::new(packet))
+        .map_err(|_| std::io::Error::new(ErrorKind::InvalidData, "DHCPv4 decoding failed"))?;
+    Ok((msg, parse_dhcp4_options(packet)))
+}
+
+const OPTION_AUTHENTICATION: u8 = 90;
+const OPTION_FORCERENEW_NONCE_CAPABLE: u8 = 145;
+/// Checks the HMAC-MD5 of a DHCPFORCERENEW, computed over the whole message
+/// with `hops`, `giaddr` and the digest zeroed, and returns its replay
+/// detection value.
+pub fn verify_forcerenew(packet: &[u8], nonce: &[u8; 16]) -> std::io::Result<u64> {
+    let invalid = |msg| std::io::Error::new(ErrorKind::InvalidData, msg);
+    let mut pos = 240usize;
+    while pos + 2 <= packet.len() {
+        match packet[pos] {
+            0 => {
+                pos += 1;
+                continue;
+            },
+            255 => break,
+            _ => {},
+        }
+        let end = pos + 2 + packet[pos + 1] as usize;
+        if end > packet.len() {
+            break;
+        }
+        if packet[pos] == OPTION_AUTHENTICATION {
+            let Some(AuthValue::HmacMd5 { replay_counter, digest }) = auth::parse(&packet[pos + 2..end]) else {
+                return Err(invalid("Unsupported DHCPFORCERENEW authentication"));
+            };
+            let mut zeroed = packet.to_vec();
+            zeroed[3] = 0;
+            zeroed[24..28].fill(0);
+            zeroed[end - 16..end].fill(0);
+            if !md5::digest_eq(&md5::hmac_md5(nonce, &zeroed), &digest) {
+                return Err(invalid("DHCPFORCERENEW authentication failed"));
+            }
+            return Ok(replay_counter);
+        }
+        pos = end;
+    }
+    Err(invalid("Unauthenticated DHCPFORCERENEW"))
+}
+
+const OPTION_IPV6_ONLY_PREFERRED: u8 = 108;
+
+/// Shortest pause honoured for IPv6-Only Preferred (RFC 8925 section 3.4).
+pub const MIN_V6ONLY_WAIT: Duration = Duration::from_secs(300);
+
+/// What [`Dhcp4Client::obtain_lease`] got from the server.
+#[derive(Debug, Clone)]
+pub enum Dhcp4Outcome {
+    /// A DHCPACK for the lease.
+    Lease(Box<Dhcp4Response>),
+    /// The server prefers IPv6-only operation (RFC 8925): leave IPv4
+    /// unconfigured and try again after V6ONLY_WAIT.
+    Ipv6Only(Duration),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Dhcp4RequestType {
     Select,
     InitReboot,
@@ -49,49 +443,131 @@ pub enum Dhcp4RequestType {
 impl Dhcp4Client {
     pub const CLIENT_PORT: u16 = 68;
     pub const SERVER_PORT: u16 = 67;
-    pub const VENDOR_CODE_NTT: u32 = 210;
+    /// How long [`Self::recv`] waits for a message.
+    pub const RECV_TIMEOUT: Duration = Duration::from_secs(15);
 
     pub fn new(local_if_mac: [u8; 6], if_name: &str) -> std::io::Result<Self> {
+        Self::with_config(local_if_mac, if_name, Dhcp4ClientConfig::default())
+    }
+
+    pub fn with_config(local_if_mac: [u8; 6], if_name: &str, config: Dhcp4ClientConfig) -> std::io::Result<Self> {
         let socket = Socket::new(Domain::IPV4, Type::DGRAM, None)?;
         socket.bind_device(Some(if_name.as_bytes()))?;
         let bind = SocketAddr::from(("0.0.0.0".parse::<IpAddr>().unwrap(), CLIENT_PORT));
         socket.bind(&bind.into())?;
         socket.set_nonblocking(false)?;
         socket.set_broadcast(true)?;
-        let socket: std::net::UdpSocket = socket.into();
-        socket.set_read_timeout(Some(Duration::from_secs(15)))?;
-        socket.set_write_timeout(Some(Duration::from_secs(15)))?;
-        Ok(Self {
-            socket,
+        let transport = UdpTransport::new(socket.into())?;
+        Ok(Self::with_transport(local_if_mac, config, Arc::new(transport)))
+    }
+
+    /// Creates a client sending and receiving through `transport` rather
+    /// than a socket of its own.
+    pub fn with_transport(local_if_mac: [u8; 6], config: Dhcp4ClientConfig, transport: Arc<dyn Transport>) -> Self {
+        Self {
+            transport,
             local_if_mac,
-        })
+            config,
+            state: Mutex::new(Dhcp4State::default()),
+            dhcp4o6_servers: None,
+        }
+    }
+
+    /// Creates a client that sends its DHCPv4 messages inside DHCPV4-QUERY
+    /// messages to the DHCP 4o6 `servers`, as learned from
+    /// [`crate::ipv6::Dhcp6Response::dhcp4o6_servers`].
+    ///
+    /// Messages go through the socket of `dhcp6`, which keeps working:
+    /// DHCPV4-RESPONSE messages are received here and all others there.
+    pub fn over_dhcp6(dhcp6: &Dhcp6Client, config: Dhcp4ClientConfig, servers: Vec<Ipv6Addr>) -> Self {
+        Self {
+            dhcp4o6_servers: Some(servers),
+            ..Self::with_transport(dhcp6.local_if_mac(), config, dhcp6.dhcp4o6_transport())
+        }
+    }
+
+    pub fn config(&self) -> &Dhcp4ClientConfig {
+        &self.config
     }
 
-    fn encode_send(&self, msg: Message, server_ip: Option<Ipv4Addr>) -> std::io::Result<()> {
+    fn state(&self) -> std::sync::MutexGuard<'_, Dhcp4State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The forcerenew nonce held for the server at `server_addr`.
+    pub fn forcerenew_nonce(&self, server_addr: Ipv4Addr) -> Option<AuthKey> {
+        match &self.state().forcerenew_nonce {
+            Some((addr, nonce)) if *addr == server_addr => Some(*nonce),
+            _ => None,
+        }
+    }
+
+    /// Takes the forcerenew nonce of a stored lease, unless one was
+    /// received since.
+    fn restore_forcerenew_nonce(&self, lease: &Dhcp4Lease) {
+        let mut state = self.state();
+        if let (None, Some(nonce)) = (&state.forcerenew_nonce, lease.forcerenew_nonce) {
+            state.forcerenew_nonce = Some((lease.server_addr, nonce));
+        }
+    }
+
+    fn client_id(&self) -> Vec<u8> {
+        match &self.config.client_id {
+            Some(id) => id.to_bytes(self.local_if_mac),
+            None => self.config.profile.dhcp4_client_id(self.local_if_mac),
+        }
+    }
+
+    fn insert_client_options(&self, msg: &mut Message, msg_type: MessageType) {
+        let profile = &self.config.profile;
+        msg.opts_mut().insert(DhcpOption::ClientIdentifier(self.client_id()));
+        if self.config.forcerenew_nonce_capable {
+            let code = OptionCode::Unknown(OPTION_FORCERENEW_NONCE_CAPABLE);
+            msg.opts_mut().insert(DhcpOption::Unknown(UnknownOption::new(code, vec![auth::ALGORITHM_HMAC_MD5])));
+        }
+        for opt in profile.dhcp4_vendor_options(msg_type, self.local_if_mac) {
+            msg.opts_mut().insert(opt);
+        }
+        for opt in &self.config.extra_options {
+            msg.opts_mut().insert(opt.clone());
+        }
+    }
+
+    fn encode_send(&self, msg: &Message, server_ip: Option<Ipv4Addr>) -> std::io::Result<()> {
         let mut buf = Vec::new();
         let mut e = Encoder::new(&mut buf);
         msg.encode(&mut e).map_err(|_| std::io::Error::new(ErrorKind::InvalidData, "DHCPv4 encoding failed"))?;
-        match server_ip {
-            None => {
-                self.socket.send_to(&buf, ("255.255.255.255", Self::SERVER_PORT))?;
-            },
-            Some(ip) => {
-                self.socket.send_to(&buf, (ip, Self::SERVER_PORT))?;
-            },
+        if let Some(servers) = &self.dhcp4o6_servers {
+            let query = dhcp4o6::encode_query(&buf, server_ip.is_some())?;
+            if servers.is_empty() {
+                self.transport.send_to(&query, (Dhcp6Client::ALL_SERVERS, Dhcp6Client::SERVER_PORT).into())?;
+            }
+            for server in servers {
+                self.transport.send_to(&query, (*server, Dhcp6Client::SERVER_PORT).into())?;
+            }
+            return Ok(());
         }
-        Ok(())
+        let dest = server_ip.unwrap_or(Ipv4Addr::BROADCAST);
+        self.transport.send_to(&buf, (dest, Self::SERVER_PORT).into())
     }
 
-    fn recv_msg(&self) -> std::io::Result<Message> {
-        let mut buf = [0u8; 1500];
-        let (nlen, _remote_addr) = self.socket.recv_from(&mut buf)?;
-        let slice = &buf[..nlen];
-        let msg = Message::decode(&mut Decoder::new(slice))
-        .map_err(|_| std::io::Error::new(ErrorKind::InvalidData, "DHCPv4 decoding failed"))?;
-        Ok(msg)
+    fn recv_packet(&self, timeout: Duration) -> std::io::Result<Vec<u8>> {
+        let packet = self.transport.recv(self.config.clock.as_ref(), timeout)?;
+        if self.dhcp4o6_servers.is_some() {
+            return dhcp4o6::decode_response(&packet);
+        }
+        Ok(packet)
+    }
+
+    fn recv_msg(&self, timeout: Duration) -> std::io::Result<(Message, Vec<RawOption>)> {
+        decode_msg(&self.recv_packet(timeout)?)
     }
 
     pub fn discover(&self) -> std::io::Result<()> {
+        self.encode_send(&self.discover_msg(), None)
+    }
+
+    fn discover_msg(&self) -> Message {
         let mut msg = Message::new(
             Ipv4Addr::from_bits(0),
             Ipv4Addr::from_bits(0),
@@ -105,19 +581,26 @@ impl Dhcp4Client {
         let flags = Flags::default().set_broadcast();
         msg.set_flags(flags);
         msg.opts_mut().insert(DhcpOption::MessageType(MessageType::Discover));
+        if self.config.rapid_commit {
+            msg.opts_mut().insert(DhcpOption::RapidCommit);
+        }
 
-        let req_list = vec![
-            OptionCode::SubnetMask,
-            OptionCode::Router,
-        ];
-        msg.opts_mut().insert(DhcpOption::ParameterRequestList(req_list));
+        msg.opts_mut().insert(DhcpOption::ParameterRequestList(self.config.discover_params.clone()));
         msg.opts_mut().insert(DhcpOption::MaxMessageSize(1200));
-        msg.opts_mut().insert(DhcpOption::ClientIdentifier(self.local_if_mac.to_vec()));
-        self.encode_send(msg, None)?;
-        Ok(())
+        self.insert_client_options(&mut msg, MessageType::Discover);
+        msg
     }
 
     pub fn request(&self, req_type: Dhcp4RequestType, req_ip: Ipv4Addr, server_id: Ipv4Addr) -> std::io::Result<()> {
+        self.encode_send(&self.request_msg(req_type, req_ip, server_id), Self::request_dest(req_type, server_id))
+    }
+
+    /// Only a renewing client unicasts to the server (RFC 2131 section 4.4.5).
+    fn request_dest(req_type: Dhcp4RequestType, server_id: Ipv4Addr) -> Option<Ipv4Addr> {
+        (req_type == Dhcp4RequestType::Renew).then_some(server_id)
+    }
+
+    fn request_msg(&self, req_type: Dhcp4RequestType, req_ip: Ipv4Addr, server_id: Ipv4Addr) -> Message {
         let mut msg = match req_type {
             Dhcp4RequestType::Select => {
                 Message::new(
@@ -162,40 +645,157 @@ impl Dhcp4Client {
             msg.opts_mut().insert(DhcpOption::ServerIdentifier(server_id));
         }
 
-        let req_list = vec![
-            OptionCode::SubnetMask,
-            OptionCode::Router,
-            OptionCode::Unknown(120),
-            OptionCode::ClasslessStaticRoute,
-            OptionCode::Unknown(125),
-        ];
-        msg.opts_mut().insert(DhcpOption::ParameterRequestList(req_list));
+        msg.opts_mut().insert(DhcpOption::ParameterRequestList(self.config.request_params.clone()));
         msg.opts_mut().insert(DhcpOption::MaxMessageSize(1200));
-        msg.opts_mut().insert(DhcpOption::ClientIdentifier(self.local_if_mac.to_vec()));
-
-        let mut opt_data: Vec<u8> = Vec::new();
-        opt_data.extend_from_slice(&u32::to_be_bytes(210));
-        opt_data.push(7);
-        opt_data.push(6);
-        opt_data.extend_from_slice(&self.local_if_mac);
-        let vendor_opt = UnknownOption::new(OptionCode::Unknown(124), opt_data);
-        msg.opts_mut().insert(DhcpOption::Unknown(vendor_opt));
-
-        let server_ip;
-        if req_type == Dhcp4RequestType::Renew {
-            server_ip = Some(server_id);
+        self.insert_client_options(&mut msg, MessageType::Request);
+        msg
+    }
+
+    /// Obtains a lease, first trying to keep `previous` with INIT-REBOOT
+    /// (RFC 2131 section 3.2) and falling back to a full DISCOVER exchange.
+    /// Messages are retransmitted with exponential backoff.
+    ///
+    /// An OFFER with IPv6-Only Preferred ends the exchange without
+    /// requesting the address, as [`Dhcp4Outcome::Ipv6Only`].
+    pub fn obtain_lease(&self, previous: Option<&Dhcp4Lease>) -> std::io::Result<Dhcp4Outcome> {
+        if let Some(lease) = previous.filter(|l| !l.is_expired(self.config.clock.now())) {
+            self.restore_forcerenew_nonce(lease);
+            let msg = self.request_msg(Dhcp4RequestType::InitReboot, lease.client_addr, lease.server_addr);
+            let res = self.exchange(&[MessageType::Ack], msg, None).and_then(|res| match res.client_addr {
+                Some(addr) if addr == lease.client_addr => Ok(res),
+                _ => Err(std::io::Error::new(ErrorKind::InvalidData, "DHCPACK for another address")),
+            });
+            match res {
+                Ok(res) => return Ok(match res.v6only_wait {
+                    Some(wait) => Dhcp4Outcome::Ipv6Only(wait),
+                    None => Dhcp4Outcome::Lease(Box::new(res)),
+                }),
+                Err(e) => log::info!("INIT-REBOOT for {} failed: {}", lease.client_addr, e),
+            }
+        }
+        let expected: &[MessageType] = if self.config.rapid_commit {
+            &[MessageType::Offer, MessageType::Ack]
         } else {
-            server_ip = None;
+            &[MessageType::Offer]
+        };
+        let offer = self.exchange(expected, self.discover_msg(), None)?;
+        if let Some(wait) = offer.v6only_wait {
+            log::info!("IPv6-only preferred, pausing DHCPv4 for {:?}", wait);
+            return Ok(Dhcp4Outcome::Ipv6Only(wait));
+        }
+        if offer.msg_type == MessageType::Ack {
+            return Ok(Dhcp4Outcome::Lease(Box::new(offer)));
+        }
+        let (Some(client_addr), Some(server_addr)) = (offer.client_addr, offer.server_addr) else {
+            return Err(std::io::Error::new(ErrorKind::InvalidData, "No server/client address in OFFER"));
+        };
+        let msg = self.request_msg(Dhcp4RequestType::Select, client_addr, server_addr);
+        let ack = self.exchange(&[MessageType::Ack], msg, None)?;
+        Ok(Dhcp4Outcome::Lease(Box::new(ack)))
+    }
+
+    /// Sends `msg` until a matching reply arrives. Retransmissions keep the
+    /// transaction ID and only update `secs`; packets for other
+    /// transactions or clients are dropped.
+    fn exchange(&self, expected_msg_types: &[MessageType], mut msg: Message, server_ip: Option<Ipv4Addr>) -> std::io::Result<Dhcp4Response> {
+        let xid = msg.xid();
+        let send = |elapsed: Duration| {
+            msg.set_secs(elapsed.as_secs().min(u16::MAX as u64) as u16);
+            self.encode_send(&msg, server_ip)
+        };
+        clock::retransmit(self.config.clock.as_ref(), RetransParams::DHCP4, send, |timeout| {
+            self.recv_reply(expected_msg_types, Some(xid), timeout)
+        })
+    }
+
+    /// Handles one DHCPFORCERENEW for `lease` (RFC 3203): waits for it, then
+    /// renews the lease. The DHCPACK carries the forcerenew nonce with its
+    /// new replay detection value, to be stored with the lease.
+    pub fn handle_forcerenew(&self, lease: &Dhcp4Lease) -> std::io::Result<Dhcp4Response> {
+        self.restore_forcerenew_nonce(lease);
+        self.recv_forcerenew(lease.server_addr)?;
+        log::info!("DHCPFORCERENEW from {}, renewing {}", lease.server_addr, lease.client_addr);
+        let msg = self.request_msg(Dhcp4RequestType::Renew, lease.client_addr, lease.server_addr);
+        self.exchange(&[MessageType::Ack], msg, Some(lease.server_addr))
+    }
+
+    /// Receives a DHCPFORCERENEW from `server_addr` and authenticates it
+    /// with the nonce from an earlier DHCPACK (RFC 6704 section 3.4).
+    pub fn recv_forcerenew(&self, server_addr: Ipv4Addr) -> std::io::Result<()> {
+        let invalid = |msg| std::io::Error::new(ErrorKind::InvalidData, msg);
+        let packet = self.recv_packet(Self::RECV_TIMEOUT)?;
+        let (msg, _) = decode_msg(&packet)?;
+        if msg.opcode() != Opcode::BootReply || msg.opts().msg_type() != Some(MessageType::ForceRenew) {
+            return Err(invalid("Unexpected message type"));
+        }
+        if msg.opts().get(OptionCode::ServerIdentifier) != Some(&DhcpOption::ServerIdentifier(server_addr)) {
+            return Err(invalid("DHCPFORCERENEW from unknown server"));
+        }
+
+        let mut state = self.state();
+        let nonce = match &mut state.forcerenew_nonce {
+            Some((addr, nonce)) if *addr == server_addr => nonce,
+            _ => return Err(invalid("No forcerenew nonce")),
+        };
+        let replay = verify_forcerenew(&packet, &nonce.key)?;
+        if !nonce.advance(replay) {
+            return Err(invalid("Replayed DHCPFORCERENEW"));
         }
-        self.encode_send(msg, server_ip)?;
         Ok(())
     }
 
     pub fn recv(&self, expected_msg_type: MessageType) -> std::io::Result<Dhcp4Response> {
-        let msg = self.recv_msg()?;
+        self.recv_any(&[expected_msg_type])
+    }
+
+    /// Receives a message of any of the given types. A DHCPACK in reply to a
+    /// DHCPDISCOVER, i.e. when both Offer and Ack are expected, is only
+    /// accepted with Rapid Commit (RFC 4039 section 4).
+    pub fn recv_any(&self, expected_msg_types: &[MessageType]) -> std::io::Result<Dhcp4Response> {
+        self.recv_reply(expected_msg_types, None, Self::RECV_TIMEOUT)
+    }
+
+    /// Like [`Self::recv_any`], also checking the transaction ID if given.
+    /// Replies must carry our hardware address and, if they echo one, our
+    /// client identifier (RFC 6842).
+    fn recv_reply(&self, expected_msg_types: &[MessageType], xid: Option<u32>, timeout: Duration) -> std::io::Result<Dhcp4Response> {
+        let (msg, raw_options) = self.recv_msg(timeout)?;
+        let received_at = ReceivedAt::now(self.config.clock.as_ref());
         if msg.opcode() != Opcode::BootReply {
             return Err(std::io::Error::new(ErrorKind::InvalidData, "Unexpected BOOTP opcode"));
         }
+        if xid.is_some_and(|xid| msg.xid() != xid) {
+            return Err(std::io::Error::new(ErrorKind::InvalidData, "Transaction ID mismatch"));
+        }
+        if msg.chaddr().get(..6) != Some(&self.local_if_mac[..]) {
+            return Err(std::io::Error::new(ErrorKind::InvalidData, "Hardware address mismatch"));
+        }
+        if let Some(DhcpOption::ClientIdentifier(id)) = msg.opts().get(OptionCode::ClientIdentifier)
+            && *id != self.client_id()
+        {
+            return Err(std::io::Error::new(ErrorKind::InvalidData, "Client identifier mismatch"));
+        }
+        let msg_type = msg.opts().msg_type()
+            .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, "No DHCP message type"))?;
+        if msg_type == MessageType::Nak && expected_msg_types.contains(&MessageType::Ack) {
+            // not dropped like a stray packet: the server refused the request
+            return Err(std::io::Error::new(ErrorKind::ConnectionAborted, "DHCPNAK"));
+        }
+        if !expected_msg_types.contains(&msg_type) {
+            return Err(std::io::Error::new(ErrorKind::InvalidData, "Unexpected message type"));
+        }
+        let rapid_commit = msg.opts().get(OptionCode::RapidCommit).is_some();
+        if msg_type == MessageType::Ack && expected_msg_types.contains(&MessageType::Offer) && !rapid_commit {
+            return Err(std::io::Error::new(ErrorKind::InvalidData, "DHCPACK to DHCPDISCOVER without Rapid Commit"));
+        }
+        // only if we said we could use one (RFC 6704 section 3.3)
+        let sent_nonce = match raw_options.iter().find(|o| o.code == OPTION_AUTHENTICATION as u16) {
+            Some(opt) if msg_type == MessageType::Ack && self.config.forcerenew_nonce_capable => match auth::parse(&opt.data) {
+                Some(AuthValue::Key(nonce)) => Some(nonce),
+                _ => None,
+            },
+            _ => None,
+        };
 
         let yiaddr = msg.yiaddr();
         // let siaddr = msg.siaddr();
@@ -209,8 +809,13 @@ impl Dhcp4Client {
         let mut sip_domain_name = None;
         let mut sip_main_number = None;
         let mut sip_add_numbers = Vec::new();
+        let mut ntt_vendor_info = None;
         let mut server_addr = None;
         let mut static_routes = Vec::new();
+        let mut classful_routes = Vec::new();
+        let mut ms_static_routes = Vec::new();
+        let mut v6only_wait = None;
+        let mut ntt_mac_mismatch = None;
 
         for (optcode, opt) in msg.opts().iter() {
             let optcode = *optcode;
@@ -225,11 +830,6 @@ impl Dhcp4Client {
                 DhcpOption::AddressLeaseTime(at) => {
                     addr_time = at;
                 },
-                DhcpOption::MessageType(msgtype) => {
-                    if msgtype != expected_msg_type {
-                        return Err(std::io::Error::new(ErrorKind::InvalidData, "Unexpected message type"));
-                    }
-                },
                 DhcpOption::ServerIdentifier(srvid) => {
                     server_addr = Some(srvid);
                 },
@@ -249,13 +849,18 @@ impl Dhcp4Client {
                         static_routes.push(route);
                     }
                 },
+                DhcpOption::StaticRoutingTable(routes) => {
+                    for (dest, gw) in routes {
+                        classful_routes.push(Dhcp4Route::classful(dest, gw));
+                    }
+                },
                 DhcpOption::Unknown(inneropt) => {
                     let code: u8 = optcode.into();
                     log::debug!("DHCPv4 optcode: {}", code);
                     let data = inneropt.data();
                     match code {
                         120 => {
-                            if data.len() < 1 {
+                            if data.is_empty() {
                                 continue;
                             }
                             let count = data[0] as usize;
@@ -271,78 +876,35 @@ impl Dhcp4Client {
                             }
                         },
                         125 => {
-                            if data.len() < 5 {
-                                continue;
-                            }
-
-                            let entnum: [u8; 4] = data[0..4].try_into().unwrap();
-                            let entnum = u32::from_be_bytes(entnum);
-                            if entnum != Self::VENDOR_CODE_NTT {
-                                log::warn!("Nonrecognized vendor code");
+                            if !self.config.profile.decodes_vendor(ntt::ENTERPRISE_NUMBER) {
                                 continue;
                             }
-                            let optlen = data[4] as usize;
-                            if (optlen + 5) > data.len() {
-                                log::warn!("Invalid NTT option length");
-                            }
-                            let mut pos = 5usize;
-                            while (pos + 1) < data.len() {
-                                let subopt_code = data[pos];
-                                let subopt_len = data[pos + 1] as usize;
-                                let startoffset = pos + 2;
-                                if startoffset >= data.len() {
-                                    break;
-                                }
-                                let endoffset = startoffset + subopt_len;
-                                if endoffset > data.len() {
-                                    break;
-                                }
-                                let subopt_data = &data[startoffset..endoffset];
-                                match subopt_code {
-                                    201 => {
-                                        // MAC address check
-                                    },
-                                    202 => {
-                                        log::debug!("Main number data: {:?}", subopt_data);
-                                        let main = String::from_utf8(subopt_data.to_vec()).unwrap_or("".to_string());
-                                        if !main.is_empty() {
-                                            sip_main_number = Some(main);
-                                        }
-                                    },
-                                    203 => {
-                                        log::debug!("Additional number data: {:?}", subopt_data);
-                                        let add = String::from_utf8(subopt_data.to_vec()).unwrap_or("".to_string());
-                                        if !add.is_empty() {
-                                            sip_add_numbers.push(add);
-                                        }
-                                    },
-                                    204 => {
-                                        let mut i = 0usize;
-                                        let mut labels = Vec::new();
-                                        while i < subopt_data.len() {
-                                            let label_len = subopt_data[i] as usize;
-                                            if label_len == 0 {
-                                                break;
-                                            }
-                                            let start_i = i + 1;
-                                            if start_i >= subopt_data.len() {
-                                                break;
-                                            }
-                                            let end_i = start_i + label_len;
-                                            if end_i > subopt_data.len() {
-                                                break;
-                                            }
-                                            let label = &subopt_data[start_i..end_i];
-                                            labels.push(String::from_utf8_lossy(label).into_owned());
-                                            i = end_i;
-                                        }
-                                        let domain = labels.join(".");
-                                        sip_domain_name = Some(domain);
-                                    },
-                                    _ => {},
-                                }
-                                pos += 2 + (subopt_len as usize);
+                            let info = match NttVendorInfo::decode_dhcp4(data) {
+                                Ok(info) => info,
+                                Err(e) => {
+                                    log::warn!("Invalid NTT vendor option: {}", e);
+                                    continue;
+                                },
+                            };
+                            if let Err(e) = info.verify_mac(self.local_if_mac) {
+                                log::warn!("{}: {:02x?}", e, info.mac_addr);
+                                ntt_mac_mismatch = info.mac_addr;
                             }
+                            sip_main_number = info.main_number.clone();
+                            sip_add_numbers = info.add_numbers.clone();
+                            sip_domain_name = info.sip_domain.clone();
+                            ntt_vendor_info = Some(info);
+                        },
+                        249 => {
+                            ms_static_routes = parse_classless_routes(data);
+                        },
+                        OPTION_IPV6_ONLY_PREFERRED if self.config.ipv6_only_preferred => {
+                            let Ok(wait) = <[u8; 4]>::try_from(data) else {
+                                log::warn!("Invalid IPv6-Only Preferred option");
+                                continue;
+                            };
+                            let wait = Duration::from_secs(u32::from_be_bytes(wait) as u64);
+                            v6only_wait = Some(wait.max(MIN_V6ONLY_WAIT));
                         },
                         _ => {},
                     }
@@ -352,8 +914,17 @@ impl Dhcp4Client {
         }
 
         let client_addr = if yiaddr == Ipv4Addr::from_bits(0) { None } else { Some(yiaddr) };
+        let forcerenew_nonce = match (sent_nonce, server_addr) {
+            (Some(nonce), Some(addr)) => {
+                self.state().forcerenew_nonce = Some((addr, nonce));
+                Some(nonce)
+            },
+            (_, Some(addr)) => self.forcerenew_nonce(addr),
+            _ => None,
+        };
 
         Ok(Dhcp4Response {
+            msg_type,
             client_addr,
             server_addr,
             router_addrs,
@@ -365,7 +936,17 @@ impl Dhcp4Client {
             sip_domain_name,
             sip_main_number,
             sip_add_numbers,
+            ntt_vendor_info,
+            ntt_mac_mismatch,
             static_routes,
+            classful_routes,
+            ms_static_routes,
+            received_at,
+            rapid_commit,
+            forcerenew_nonce,
+            v6only_wait,
+            custom_options: self.config.decoders.decode_dhcp4(&raw_options),
+            raw_options,
         })
     }
 }
@@ -1,19 +1,215 @@
 
 use std::io::ErrorKind;
 use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use dhcproto::v6::{DhcpOption, DhcpOptions, IAPrefix, OptionCode, Status, StatusCode, UnknownOption, IAPD};
+use dhcproto::v6::{DhcpOption, DhcpOptions, IAAddr, IAPrefix, OptionCode, Status, StatusCode, UnknownOption, IANA, IAPD};
 use dhcproto::{Decodable, Decoder, Encodable, Encoder};
+use ipnet::Ipv6Net;
 use socket2::{Socket, Domain, Type};
 
+use crate::auth::{self, AuthKey, AuthValue};
+use crate::clock::{self, BootInstant, Clock, RetransParams, SystemClock};
+use crate::dhcp4o6;
+use crate::identity::Duid;
+use crate::lease::{Dhcp6Lease, LeaseTimes, ReceivedAt};
+use crate::md5;
+use crate::ntt::{self, NttVendorInfo};
+use crate::options::{parse_dhcp6_options, CustomOptions, OptionDecoders, RawOption};
+use crate::profile::{NttNgnProfile, ProvisioningProfile};
+use crate::softwire::{self, S46Container};
+use crate::transport::{Transport, UdpTransport};
+
 pub use dhcproto::v6::MessageType;
 
 #[derive(Debug)]
 pub struct Dhcp6Client {
-    socket: std::net::UdpSocket,
+    transport: Arc<dyn Transport>,
+    /// The same socket, for a DHCPv4 client over DHCPv6.
+    dhcp4o6_transport: Arc<dyn Transport>,
     local_if_mac: [u8; 6],
     local_ll_addr: Ipv6Addr,
+    config: Dhcp6ClientConfig,
+    state: Mutex<Dhcp6State>,
+}
+
+/// What the client remembers between sending and receiving.
+#[derive(Debug, Default)]
+struct Dhcp6State {
+    /// IAID of the IA_PD in the last message sent.
+    ia_id: Option<u32>,
+    /// Server DUID and the reconfigure key (RFC 8415 section 20.4) it sent.
+    reconfigure_key: Option<(Vec<u8>, AuthKey)>,
+    /// Server DUID and the address it allowed unicast to (option 12).
+    server_unicast: Option<(Vec<u8>, Ipv6Addr)>,
+    /// Address last assigned in an IA_NA, asked for again in later messages.
+    na: Option<NaAddress>,
+}
+
+/// What a server asks the client to do in a Reconfigure message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReconfigureRequest {
+    Renew,
+    Rebind,
+    InformationRequest,
+}
+
+/// Options requested from and sent to the server.
+///
+/// The ORO starts out as the profile's; the default profile is
+/// [`NttNgnProfile`], matching what the client has always sent.
+///
+/// The client only has its link-local address to send from, so it honours
+/// Server Unicast (RFC 8415 section 18.2.10) only for link-local server
+/// addresses. Servers usually advertise a global one; messages to those
+/// keep going to All_DHCP_Relay_Agents_and_Servers, which servers must
+/// accept anyway.
+#[derive(Debug, Clone)]
+pub struct Dhcp6ClientConfig {
+    pub profile: Arc<dyn ProvisioningProfile>,
+    /// Client DUID; when unset the profile derives one from the MAC address.
+    pub duid: Option<Duid>,
+    /// Option Request Option sent in Solicit, Request, Renew, Rebind and
+    /// Information-request.
+    pub oro: Vec<OptionCode>,
+    /// Decoders for options the crate does not interpret itself.
+    pub decoders: OptionDecoders,
+    /// Options appended verbatim to every message sent to servers.
+    pub extra_options: Vec<DhcpOption>,
+    /// Time source for retransmission and lease timers.
+    pub clock: Arc<dyn Clock>,
+    /// Sends Rapid Commit in Solicit, so that a server may complete the
+    /// binding with a single Reply (RFC 8415 section 18.2.1).
+    pub rapid_commit: bool,
+    /// Sends Reconfigure Accept, telling servers the client handles
+    /// Reconfigure messages.
+    pub reconfigure_accept: bool,
+    /// How long to try rebinding a prefix held from before a restart or
+    /// link change before giving up on it, at most its valid lifetime.
+    pub rebind_timeout: Duration,
+    /// Also asks for an address, in an IA_NA with the IA_PD's IAID.
+    pub ia_na: bool,
+}
+
+impl Default for Dhcp6ClientConfig {
+    fn default() -> Self {
+        Self::new(Arc::new(NttNgnProfile))
+    }
+}
+
+impl Dhcp6ClientConfig {
+    /// RFC 8415 sets no bound for this Rebind, so it uses Confirm's
+    /// (CNF_MAX_RD, section 7.6).
+    pub const DEFAULT_REBIND_TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub fn new(profile: Arc<dyn ProvisioningProfile>) -> Self {
+        Self {
+            duid: None,
+            oro: profile.dhcp6_oro(),
+            decoders: OptionDecoders::new(),
+            extra_options: Vec::new(),
+            clock: Arc::new(SystemClock),
+            rapid_commit: false,
+            reconfigure_accept: false,
+            rebind_timeout: Self::DEFAULT_REBIND_TIMEOUT,
+            ia_na: false,
+            profile,
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_rapid_commit(mut self) -> Self {
+        self.rapid_commit = true;
+        self
+    }
+
+    pub fn with_reconfigure_accept(mut self) -> Self {
+        self.reconfigure_accept = true;
+        self
+    }
+
+    pub fn with_ia_na(mut self) -> Self {
+        self.ia_na = true;
+        self
+    }
+
+    pub fn with_rebind_timeout(mut self, timeout: Duration) -> Self {
+        self.rebind_timeout = timeout;
+        self
+    }
+
+    pub fn with_duid(mut self, duid: Duid) -> Self {
+        self.duid = Some(duid);
+        self
+    }
+
+    /// Adds `code` to the ORO, if not already present.
+    pub fn with_oro(mut self, code: OptionCode) -> Self {
+        if !self.oro.contains(&code) {
+            self.oro.push(code);
+        }
+        self
+    }
+
+    /// Requests AFTR-Name and the MAP-E, MAP-T and lw4o6 containers, as
+    /// IPoE services providing IPv4 over IPv6 need.
+    pub fn with_softwire_options(self) -> Self {
+        self.with_oro(OptionCode::AftrName)
+            .with_oro(OptionCode::S46ContMape)
+            .with_oro(OptionCode::S46ContMapt)
+            .with_oro(OptionCode::S46ContLw)
+    }
+
+    /// Requests the DHCP 4o6 Server Address option (RFC 7341), for use
+    /// with [`crate::ipv4::Dhcp4Client::over_dhcp6`].
+    pub fn with_dhcp4o6(self) -> Self {
+        self.with_oro(OptionCode::Dhcp4ODhcp6Server)
+    }
+
+    /// Requests PD_EXCLUDE (RFC 6603), telling the server the client
+    /// handles excluded prefixes.
+    pub fn with_pd_exclude(self) -> Self {
+        self.with_oro(OptionCode::PdExclude)
+    }
+
+    /// Adds an option to send, replacing any earlier option with the same code.
+    pub fn with_option(mut self, opt: DhcpOption) -> Self {
+        let code = OptionCode::from(&opt);
+        self.extra_options.retain(|o| OptionCode::from(o) != code);
+        self.extra_options.push(opt);
+        self
+    }
+
+    /// User Class (option 15). Fails if a class is longer than 65535 Bytes.
+    pub fn with_user_classes(self, classes: &[&[u8]]) -> std::io::Result<Self> {
+        let mut data = Vec::new();
+        for class in classes {
+            let len = u16::try_from(class.len())
+                .map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "User class too long"))?;
+            data.extend_from_slice(&len.to_be_bytes());
+            data.extend_from_slice(class);
+        }
+        Ok(self.with_option(DhcpOption::Unknown(UnknownOption::new(OptionCode::UserClass, data))))
+    }
+
+    /// Client FQDN (option 39, RFC 4704), asking the server to perform the AAAA RR update.
+    pub fn with_client_fqdn(self, fqdn: &str) -> std::io::Result<Self> {
+        let mut data = vec![0x01];
+        for label in fqdn.trim_end_matches('.').split('.') {
+            if label.is_empty() || label.len() > 63 {
+                return Err(std::io::Error::new(ErrorKind::InvalidInput, "Invalid FQDN"));
+            }
+            data.push(label.len() as u8);
+            data.extend_from_slice(label.as_bytes());
+        }
+        data.push(0);
+        Ok(self.with_option(DhcpOption::Unknown(UnknownOption::new(OptionCode::ClientFqdn, data))))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -24,17 +220,226 @@ pub struct PdPrefix {
     pub valid_lifetime: u32,
     pub t1: u32,
     pub t2: u32,
+    /// Prefix the server excluded from the delegation for the link to
+    /// the client (RFC 6603).
+    pub excluded: Option<Ipv6Net>,
+}
+
+/// Prefix suggested to the server in an IA_PD (RFC 8415 section 18.2.1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PdHint {
+    pub prefix: Ipv6Addr,
+    pub prefix_len: u8,
+}
+
+impl PdHint {
+    /// Asks for a delegation of the given size, e.g. a /56 or /48.
+    pub fn length(prefix_len: u8) -> Self {
+        Self {
+            prefix: Ipv6Addr::UNSPECIFIED,
+            prefix_len,
+        }
+    }
+
+    /// Asks for a specific prefix, e.g. one held before a reboot.
+    pub fn prefix(prefix: Ipv6Addr, prefix_len: u8) -> Self {
+        Self {
+            prefix,
+            prefix_len,
+        }
+    }
+
+    fn to_option(self) -> DhcpOption {
+        DhcpOption::IAPrefix(IAPrefix {
+            preferred_lifetime: 0,
+            valid_lifetime: 0,
+            prefix_ip: self.prefix,
+            prefix_len: self.prefix_len,
+            opts: DhcpOptions::new(),
+        })
+    }
+}
+
+impl PdPrefix {
+    /// Lease deadlines counted from `received_at`.
+    pub fn lease_times(&self, received_at: ReceivedAt) -> LeaseTimes {
+        LeaseTimes::new(received_at, self.preferred_lifetime, self.valid_lifetime, self.t1, self.t2)
+    }
+
+    pub fn net(&self) -> Ipv6Net {
+        Ipv6Net::new(self.prefix, self.prefix_len.min(128)).unwrap().trunc()
+    }
+
+    /// The /64s of the delegated prefix usable on LANs, leaving out any
+    /// that overlap the excluded prefix.
+    pub fn lan_subnets(&self) -> impl Iterator<Item = Ipv6Net> + '_ {
+        self.net().subnets(64).into_iter()
+            .flatten()
+            .filter(move |net| self.excluded.is_none_or(|ex| !ex.contains(net) && !net.contains(&ex)))
+    }
+}
+
+/// Decodes a PD_EXCLUDE option (RFC 6603 section 4.2) within the IA
+/// Prefix `delegated`.
+pub fn decode_pd_exclude(delegated: Ipv6Net, data: &[u8]) -> std::io::Result<Ipv6Net> {
+    let invalid = || std::io::Error::new(ErrorKind::InvalidData, "Invalid PD_EXCLUDE option");
+    let (&len, subnet_id) = data.split_first().ok_or_else(invalid)?;
+    let base = delegated.prefix_len();
+    if len <= base || len > 128 || subnet_id.len() != (len - base).div_ceil(8) as usize {
+        return Err(invalid());
+    }
+    let mut bits = [0u8; 16];
+    bits[..subnet_id.len()].copy_from_slice(subnet_id);
+    let id = u128::from_be_bytes(bits) >> base;
+    let addr = u128::from(delegated.network()) | id;
+    Ok(Ipv6Net::new(Ipv6Addr::from(addr), len).unwrap().trunc())
+}
+
+/// An address assigned in an IA_NA.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NaAddress {
+    pub ia_id: u32,
+    pub addr: Ipv6Addr,
+    pub preferred_lifetime: u32,
+    pub valid_lifetime: u32,
+    pub t1: u32,
+    pub t2: u32,
+}
+
+impl NaAddress {
+    pub fn lease_times(&self, received_at: ReceivedAt) -> LeaseTimes {
+        LeaseTimes::new(received_at, self.preferred_lifetime, self.valid_lifetime, self.t1, self.t2)
+    }
+}
+
+/// Whether a binding survived a link change (RFC 8415 section 18.2.12).
+#[derive(Debug, Clone)]
+pub enum BindingCheck {
+    /// Still valid; after a Rebind, carries the Reply with new lifetimes.
+    Valid(Option<Box<Dhcp6Response>>),
+    /// Not valid on this link any more; start over with Solicit.
+    Invalid,
+    /// No server answered; keep using the binding until it expires.
+    NoReply,
+    /// A server answered with an error status. Unlike [`Self::Invalid`],
+    /// the binding may still be valid; treat it as [`Self::NoReply`].
+    Failed(StatusCode),
+}
+
+/// Carried by the [`ErrorKind::ConnectionAborted`] error for a reply with
+/// an error status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusError(pub StatusCode);
+
+impl std::fmt::Display for StatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DHCP error {:?}: {}", self.0.status, self.0.msg)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+impl StatusError {
+    /// Finds the status in an error returned by the client, if any.
+    pub fn of(e: &std::io::Error) -> Option<&Self> {
+        e.get_ref()?.downcast_ref()
+    }
+}
+
+impl From<&PdPrefix> for PdHint {
+    fn from(pd: &PdPrefix) -> Self {
+        Self::prefix(pd.prefix, pd.prefix_len)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Dhcp6Response {
+    pub msg_type: MessageType,
     pub client_id: Vec<u8>,
     pub server_id: Vec<u8>,
     pub pd: Option<PdPrefix>,
+    pub na: Option<NaAddress>,
     pub nameserver_addrs: Vec<Ipv6Addr>,
     pub domain_search_list: Vec<String>,
     pub sip_server_addrs: Vec<Ipv6Addr>,
     pub sntp_server_addrs: Vec<Ipv6Addr>,
+    pub ntt_vendor_info: Option<NttVendorInfo>,
+    /// The MAC address echoed in the NTT vendor option, if it is not ours.
+    pub ntt_mac_mismatch: Option<[u8; 6]>,
+    /// DS-Lite AFTR name (option 64).
+    pub aftr_name: Option<String>,
+    pub map_e: Option<S46Container>,
+    pub map_t: Option<S46Container>,
+    pub lw4o6: Option<S46Container>,
+    /// DHCP 4o6 servers (option 88); empty means multicast.
+    pub dhcp4o6_servers: Option<Vec<Ipv6Addr>>,
+    pub received_at: ReceivedAt,
+    /// Whether the reply carried Rapid Commit.
+    pub rapid_commit: bool,
+    /// Reconfigure key sent by the server, or the one it sent earlier.
+    pub reconfigure_key: Option<AuthKey>,
+    /// Address the server accepts unicast messages on (option 12); only
+    /// used if link-local, see [`Dhcp6ClientConfig`].
+    pub server_unicast: Option<Ipv6Addr>,
+    /// Every top-level option in the reply, as received.
+    pub raw_options: Vec<RawOption>,
+    /// Results of the decoders registered in [`Dhcp6ClientConfig::decoders`].
+    pub custom_options: CustomOptions,
+}
+
+impl Dhcp6Response {
+    /// Returns the first option with `code`; DHCPv6 options may repeat.
+    pub fn raw_option(&self, code: u16) -> Option<&[u8]> {
+        self.raw_options.iter().find(|o| o.code == code).map(|o| o.data.as_slice())
+    }
+}
+
+/// Returns the wire-format payload of an option, without code and length.
+fn option_payload(opt: &DhcpOption) -> std::io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    opt.encode(&mut Encoder::new(&mut buf))
+        .map_err(|_| std::io::Error::new(ErrorKind::InvalidData, "DHCPv6 encoding failed"))?;
+    Ok(buf.split_off(4))
+}
+
+fn decode_msg(packet: &[u8]) -> std::io::Result<(dhcproto::v6::Message, Vec<RawOption>)> {
+    let msg = dhcproto::v6::Message::decode(&mut Decoder::new(packet))
+        .map_err(|_| std::io::Error::new(ErrorKind::InvalidData, "DHCPv6 decoding failed"))?;
+    let raw_options = parse_dhcp6_options(&packet[4..]);
+    Ok((msg, raw_options))
+}
+
+/// Checks the HMAC-MD5 of a Reconfigure, computed over the whole message
+/// with the digest zeroed, and returns its replay detection value.
+pub fn verify_reconfigure(packet: &[u8], key: &[u8; 16]) -> std::io::Result<u64> {
+    let invalid = |msg| std::io::Error::new(ErrorKind::InvalidData, msg);
+    let mut pos = 4usize;
+    while pos + 4 <= packet.len() {
+        let code = u16::from_be_bytes([packet[pos], packet[pos + 1]]);
+        let end = pos + 4 + u16::from_be_bytes([packet[pos + 2], packet[pos + 3]]) as usize;
+        if end > packet.len() {
+            break;
+        }
+        if code == u16::from(OptionCode::Authentication) {
+            let Some(AuthValue::HmacMd5 { replay_counter, digest }) = auth::parse(&packet[pos + 4..end]) else {
+                return Err(invalid("Unsupported Reconfigure authentication"));
+            };
+            let mut zeroed = packet.to_vec();
+            zeroed[end - 16..end].fill(0);
+            if !md5::digest_eq(&md5::hmac_md5(key, &zeroed), &digest) {
+                return Err(invalid("Reconfigure authentication failed"));
+            }
+            return Ok(replay_counter);
+        }
+        pos = end;
+    }
+    Err(invalid("Unauthenticated Reconfigure"))
+}
+
+/// Elapsed Time option value: hundredths of a second, saturating
+/// (RFC 8415 section 21.9).
+fn elapsed_time(elapsed: Duration) -> u16 {
+    (elapsed.as_millis() / 10).min(0xffff) as u16
 }
 
 pub fn ipv6_ll_to_mac(ll_addr: Ipv6Addr) -> [u8; 6] {
@@ -59,9 +464,15 @@ pub fn ipv6_ll_to_mac(ll_addr: Ipv6Addr) -> [u8; 6] {
 impl Dhcp6Client {
     pub const CLIENT_PORT: u16 = 546;
     pub const SERVER_PORT: u16 = 547;
-    pub const VENDOR_CODE_NTT: u32 = 210;
+    /// How long [`Self::recv`] waits for a message.
+    pub const RECV_TIMEOUT: Duration = Duration::from_secs(15);
+    pub const ALL_SERVERS: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 1, 2);
 
     pub fn new(local_ll_address: Ipv6Addr, local_if_mac: [u8; 6], if_name: &str) -> std::io::Result<Self> {
+        Self::with_config(local_ll_address, local_if_mac, if_name, Dhcp6ClientConfig::default())
+    }
+
+    pub fn with_config(local_ll_address: Ipv6Addr, local_if_mac: [u8; 6], if_name: &str, config: Dhcp6ClientConfig) -> std::io::Result<Self> {
         if !local_ll_address.is_unicast_link_local() {
             return Err(std::io::Error::new(ErrorKind::InvalidInput, "Invalid IPv6 link-local address"));
         }
@@ -70,28 +481,64 @@ impl Dhcp6Client {
         socket.bind_device(Some(if_name.as_bytes()))?;
         socket.bind(&(SocketAddr::V6(SocketAddrV6::new(local_ll_address, Self::CLIENT_PORT, 0, 0)).into()))?;
         socket.set_nonblocking(false)?;
-        let socket: std::net::UdpSocket = socket.into();
-        socket.set_read_timeout(Some(Duration::from_secs(15)))?;
-        socket.set_write_timeout(Some(Duration::from_secs(15)))?;
+        let transport = UdpTransport::new(socket.into())?;
+        Self::with_transport(local_ll_address, local_if_mac, config, Arc::new(transport))
+    }
+
+    /// Creates a client sending and receiving through `transport` rather
+    /// than a socket of its own.
+    pub fn with_transport(local_ll_address: Ipv6Addr, local_if_mac: [u8; 6], config: Dhcp6ClientConfig, transport: Arc<dyn Transport>) -> std::io::Result<Self> {
+        if !local_ll_address.is_unicast_link_local() {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "Invalid IPv6 link-local address"));
+        }
+        let (transport, dhcp4o6_transport) = dhcp4o6::SharedTransport::split(transport);
         Ok(Self {
-            socket,
+            transport: Arc::new(transport),
+            dhcp4o6_transport: Arc::new(dhcp4o6_transport),
             local_if_mac,
             local_ll_addr: local_ll_address,
+            config,
+            state: Mutex::new(Dhcp6State::default()),
         })
     }
 
-    fn encode_send(&self, msg: dhcproto::v6::Message) -> std::io::Result<()> {
+    pub fn config(&self) -> &Dhcp6ClientConfig {
+        &self.config
+    }
+
+    pub fn local_if_mac(&self) -> [u8; 6] {
+        self.local_if_mac
+    }
+
+    /// This client's socket, for [`crate::ipv4::Dhcp4Client::over_dhcp6`].
+    pub(crate) fn dhcp4o6_transport(&self) -> Arc<dyn Transport> {
+        self.dhcp4o6_transport.clone()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, Dhcp6State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn insert_client_options(&self, msg: &mut dhcproto::v6::Message) {
+        if self.config.reconfigure_accept {
+            msg.opts_mut().insert(DhcpOption::ReconfAccept);
+        }
+        for opt in self.config.profile.dhcp6_vendor_options(msg.msg_type(), self.local_if_mac) {
+            msg.opts_mut().insert(opt);
+        }
+        for opt in &self.config.extra_options {
+            msg.opts_mut().insert(opt.clone());
+        }
+    }
+
+    /// Sends to `dest`, or to All_DHCP_Relay_Agents_and_Servers if unset.
+    fn encode_send(&self, msg: &dhcproto::v6::Message, dest: Option<Ipv6Addr>) -> std::io::Result<()> {
         let mut buf = Vec::with_capacity(1500);
         let mut e = Encoder::new(&mut buf);
         msg.encode(&mut e).map_err(|_| std::io::Error::new(ErrorKind::InvalidData, "DHCPv6 encoding failed"))?;
         let buf = e.buffer_filled();
-        let sentlen = self.socket.send_to(buf, ("ff02::1:2", Self::SERVER_PORT))?;
-        if sentlen < buf.len() {
-            log::error!("Packet ({} Bytes) not sent in whole", buf.len());
-        } else {
-            log::debug!("Packet ({} Bytes) sent", buf.len());
-        }
-        Ok(())
+        let dest = dest.unwrap_or(Self::ALL_SERVERS);
+        self.transport.send_to(buf, (dest, Self::SERVER_PORT).into())
     }
 
     pub fn local_ll(&self) -> std::io::Result<Ipv6Addr> {
@@ -101,100 +548,485 @@ impl Dhcp6Client {
     }
 
     pub fn local_duid(&self) -> std::io::Result<Vec<u8>> {
-        let mac = self.local_if_mac;
-        let mut duid: Vec<u8> = vec![0x00, 0x03, 0x00, 0x01];
-        duid.extend_from_slice(&mac);
-        Ok(duid)
+        match &self.config.duid {
+            Some(duid) => Ok(duid.to_bytes()),
+            None => Ok(self.config.profile.dhcp6_duid(self.local_if_mac)),
+        }
+    }
+
+    /// The reconfigure key held for the server with DUID `server_id`.
+    pub fn reconfigure_key(&self, server_id: &[u8]) -> Option<AuthKey> {
+        match &self.state().reconfigure_key {
+            Some((id, key)) if id.as_slice() == server_id => Some(*key),
+            _ => None,
+        }
+    }
+
+    /// Takes the reconfigure key of a stored lease, unless one was received
+    /// since.
+    fn restore_reconfigure_key(&self, lease: &Dhcp6Lease) {
+        let mut state = self.state();
+        if let (None, Some(key)) = (&state.reconfigure_key, lease.reconfigure_key) {
+            state.reconfigure_key = Some((lease.server_id.clone(), key));
+        }
     }
 
+
     pub fn solicit_pd(&self, elapsed: Duration, ia_id: u32) -> std::io::Result<()> {
+        self.solicit_pd_with_hint(elapsed, ia_id, None)
+    }
+
+    /// Solicits with an IA Prefix hint, e.g. `PdHint::length(56)` or the
+    /// prefix held before a reboot.
+    pub fn solicit_pd_with_hint(&self, elapsed: Duration, ia_id: u32, hint: Option<PdHint>) -> std::io::Result<()> {
+        let mut msg = self.solicit_msg(ia_id, hint)?;
+        self.send_msg(&mut msg, elapsed, None)
+    }
+
+    fn solicit_msg(&self, ia_id: u32, hint: Option<PdHint>) -> std::io::Result<dhcproto::v6::Message> {
+        self.state().ia_id = Some(ia_id);
         let duid = self.local_duid()?;
         let mut msg = dhcproto::v6::Message::new(dhcproto::v6::MessageType::Solicit);
         msg.opts_mut().insert(DhcpOption::ClientId(duid));
-        msg.opts_mut().insert(DhcpOption::ElapsedTime(elapsed.as_millis().try_into().unwrap_or(0)));
+        msg.opts_mut().insert(DhcpOption::ORO(dhcproto::v6::ORO {
+            opts: self.config.oro.clone(),
+        }));
+        if self.config.rapid_commit {
+            msg.opts_mut().insert(DhcpOption::RapidCommit);
+        }
+        let mut pd_options = DhcpOptions::new();
+        if let Some(hint) = hint {
+            pd_options.insert(hint.to_option());
+        }
         msg.opts_mut().insert(DhcpOption::IAPD(IAPD {
             id: ia_id,
             t1: 0,
             t2: 0,
-            opts: DhcpOptions::new(),
+            opts: pd_options,
         }));
-        self.encode_send(msg)?;
-        Ok(())
+        self.insert_ia_na(&mut msg, ia_id);
+        self.insert_client_options(&mut msg);
+        Ok(msg)
+    }
+
+    /// Adds the IA_NA if configured, with the address held in it, if any.
+    fn insert_ia_na(&self, msg: &mut dhcproto::v6::Message, ia_id: u32) {
+        if !self.config.ia_na {
+            return;
+        }
+        let mut na_options = DhcpOptions::new();
+        if let Some(na) = self.state().na.as_ref().filter(|na| na.ia_id == ia_id) {
+            na_options.insert(DhcpOption::IAAddr(IAAddr {
+                addr: na.addr,
+                preferred_life: 0,
+                valid_life: 0,
+                opts: DhcpOptions::new(),
+            }));
+        }
+        msg.opts_mut().insert(DhcpOption::IANA(IANA {
+            id: ia_id,
+            t1: 0,
+            t2: 0,
+            opts: na_options,
+        }));
+    }
+
+    /// Sets Elapsed Time, the only option that changes between
+    /// retransmissions, and sends.
+    fn send_msg(&self, msg: &mut dhcproto::v6::Message, elapsed: Duration, dest: Option<Ipv6Addr>) -> std::io::Result<()> {
+        msg.opts_mut().insert(DhcpOption::ElapsedTime(elapsed_time(elapsed)));
+        log::debug!("{:?}: {:?}", msg.msg_type(), msg);
+        self.encode_send(msg, dest)
     }
 
     pub fn request_pd(&self, elapsed: Duration, ia_id: u32, server_id: Vec<u8>, pd: PdPrefix) -> std::io::Result<()> {
-        let duid = self.local_duid()?;
-        let mut msg = dhcproto::v6::Message::new(dhcproto::v6::MessageType::Request);
-        msg.opts_mut().insert(DhcpOption::ClientId(duid));
-        msg.opts_mut().insert(DhcpOption::ServerId(server_id));
-        let mut oro = dhcproto::v6::ORO {
-            opts: Vec::new(),
-        };
-        oro.opts.push(OptionCode::IAPD);
-        oro.opts.push(OptionCode::SipServerA);
-        oro.opts.push(OptionCode::SntpServers);
-        oro.opts.push(OptionCode::DomainNameServers);
-        oro.opts.push(OptionCode::DomainSearchList);
-        msg.opts_mut().insert(DhcpOption::ORO(oro));
-        msg.opts_mut().insert(DhcpOption::ElapsedTime(elapsed.as_millis().try_into().unwrap_or(0)));
-
-        let mut data1 = Vec::new();
-        data1.extend_from_slice(&u32::to_be_bytes(Self::VENDOR_CODE_NTT));
-        data1.extend_from_slice(&u16::to_be_bytes(6));
-        data1.extend_from_slice(&self.local_if_mac);
-        msg.opts_mut().insert(DhcpOption::Unknown(UnknownOption::new(
-            OptionCode::VendorClass,
-            data1,
-        )));
-        // msg.opts_mut().insert(DhcpOption::VendorClass(VendorClass {
-        //     num: Self::VENDOR_CODE_NTT,
-        //     data: vec![self.local_if_mac.to_vec()], // MAC address
-        // }));
+        let mut msg = self.request_msg(ia_id, server_id.clone(), &pd)?;
+        self.send_msg(&mut msg, elapsed, self.unicast_addr(&server_id))
+    }
 
-        let mut pd_options = DhcpOptions::new();
+    fn request_msg(&self, ia_id: u32, server_id: Vec<u8>, pd: &PdPrefix) -> std::io::Result<dhcproto::v6::Message> {
         let mut prefix_options = DhcpOptions::new();
         prefix_options.insert(DhcpOption::StatusCode(StatusCode {
             status: dhcproto::v6::Status::Success,
             msg: "".to_string(),
         }));
-        pd_options.insert(DhcpOption::IAPrefix(IAPrefix {
+        let prefix = DhcpOption::IAPrefix(IAPrefix {
             preferred_lifetime: pd.preferred_lifetime,
             valid_lifetime: pd.valid_lifetime,
             prefix_ip: pd.prefix,
             prefix_len: pd.prefix_len,
             opts: prefix_options,
+        });
+        self.pd_msg(MessageType::Request, ia_id, Some(server_id), prefix)
+    }
+
+    /// Requests a hinted prefix instead of one taken from an Advertise.
+    pub fn request_pd_with_hint(&self, elapsed: Duration, ia_id: u32, server_id: Vec<u8>, hint: PdHint) -> std::io::Result<()> {
+        let dest = self.unicast_addr(&server_id);
+        let mut msg = self.pd_msg(MessageType::Request, ia_id, Some(server_id), hint.to_option())?;
+        self.send_msg(&mut msg, elapsed, dest)
+    }
+
+    /// Rebinds a delegated prefix with any server, e.g. one held across a
+    /// reboot (RFC 8415 section 18.2.5).
+    pub fn rebind_pd(&self, elapsed: Duration, ia_id: u32, pd: &PdPrefix) -> std::io::Result<()> {
+        let mut msg = self.pd_msg(MessageType::Rebind, ia_id, None, PdHint::from(pd).to_option())?;
+        self.send_msg(&mut msg, elapsed, None)
+    }
+
+    /// Renews a delegated prefix with the server that granted it
+    /// (RFC 8415 section 18.2.4).
+    pub fn renew_pd(&self, elapsed: Duration, ia_id: u32, server_id: Vec<u8>, pd: &PdPrefix) -> std::io::Result<()> {
+        let dest = self.unicast_addr(&server_id);
+        let mut msg = self.pd_msg(MessageType::Renew, ia_id, Some(server_id), PdHint::from(pd).to_option())?;
+        self.send_msg(&mut msg, elapsed, dest)
+    }
+
+    /// Gives a delegated prefix back to the server (RFC 8415 section 18.2.7).
+    pub fn release_pd(&self, elapsed: Duration, ia_id: u32, server_id: Vec<u8>, pd: &PdPrefix) -> std::io::Result<()> {
+        let dest = self.unicast_addr(&server_id);
+        let mut msg = self.pd_msg(MessageType::Release, ia_id, Some(server_id), PdHint::from(pd).to_option())?;
+        self.send_msg(&mut msg, elapsed, dest)
+    }
+
+    /// Asks for configuration without any IA (RFC 8415 section 18.2.6).
+    pub fn information_request(&self, elapsed: Duration) -> std::io::Result<()> {
+        let mut msg = self.information_request_msg()?;
+        self.send_msg(&mut msg, elapsed, None)
+    }
+
+    fn information_request_msg(&self) -> std::io::Result<dhcproto::v6::Message> {
+        let duid = self.local_duid()?;
+        let mut msg = dhcproto::v6::Message::new(MessageType::InformationRequest);
+        msg.opts_mut().insert(DhcpOption::ClientId(duid));
+        msg.opts_mut().insert(DhcpOption::ORO(dhcproto::v6::ORO {
+            opts: self.config.oro.iter().copied().filter(|c| *c != OptionCode::IAPD).collect(),
         }));
+        self.insert_client_options(&mut msg);
+        Ok(msg)
+    }
+
+    fn pd_msg(&self, msg_type: MessageType, ia_id: u32, server_id: Option<Vec<u8>>, prefix: DhcpOption) -> std::io::Result<dhcproto::v6::Message> {
+        self.state().ia_id = Some(ia_id);
+        let duid = self.local_duid()?;
+        let mut msg = dhcproto::v6::Message::new(msg_type);
+        msg.opts_mut().insert(DhcpOption::ClientId(duid));
+        if let Some(server_id) = server_id {
+            msg.opts_mut().insert(DhcpOption::ServerId(server_id));
+        }
+        // RFC 8415 section 18.2.7: no ORO in Release
+        if msg_type != MessageType::Release {
+            msg.opts_mut().insert(DhcpOption::ORO(dhcproto::v6::ORO {
+                opts: self.config.oro.clone(),
+            }));
+        }
+
+        let mut pd_options = DhcpOptions::new();
+        pd_options.insert(prefix);
         msg.opts_mut().insert(DhcpOption::IAPD(IAPD {
             id: ia_id,
             t1: 0,
             t2: 0,
             opts: pd_options,
         }));
-        log::debug!("REQUEST: {:?}", &msg);
-        self.encode_send(msg)?;
-        Ok(())
+        self.insert_ia_na(&mut msg, ia_id);
+        self.insert_client_options(&mut msg);
+        Ok(msg)
     }
 
-    fn recv_msg(&self) -> std::io::Result<dhcproto::v6::Message> {
-        let mut buf = [0u8; 1500];
-        let (nlen, _remote_addr) = self.socket.recv_from(&mut buf)?;
-        let slice = &buf[..nlen];
-        let msg = dhcproto::v6::Message::decode(&mut Decoder::new(slice))
-        .map_err(|_| std::io::Error::new(ErrorKind::InvalidData, "DHCPv6 decoding failed"))?;
+    /// Where to unicast to the server with DUID `server_id`, if it allowed
+    /// it. Our socket only has a link-local source, which is no use to a
+    /// server further away, so any wider address falls back to multicast.
+    fn unicast_addr(&self, server_id: &[u8]) -> Option<Ipv6Addr> {
+        match &self.state().server_unicast {
+            Some((id, addr)) if id == server_id && addr.is_unicast_link_local() => Some(*addr),
+            Some((id, addr)) if id == server_id => {
+                log::debug!("Not unicasting to {} from a link-local address", addr);
+                None
+            },
+            _ => None,
+        }
+    }
+
+    /// Obtains a delegated prefix, first trying to rebind the one in
+    /// `previous` and falling back to a full Solicit exchange. Messages
+    /// are retransmitted as in RFC 8415 section 15.
+    pub fn obtain_pd(&self, ia_id: u32, previous: Option<&Dhcp6Lease>) -> std::io::Result<Dhcp6Response> {
+        let now = self.config.clock.now();
+        // a binding stored under another DUID or IAID is not ours to reuse
+        let duid = self.local_duid()?;
+        let previous = previous.filter(|l| l.ia_id == ia_id && l.client_id == duid);
+        if let Some(lease) = previous {
+            self.restore_reconfigure_key(lease);
+        }
+        if let Some(lease) = previous.filter(|l| !l.is_expired(now)) {
+            let pd = lease.pd(now);
+            let msg = self.pd_msg(MessageType::Rebind, ia_id, None, PdHint::from(&pd).to_option())?;
+            match self.exchange(self.rebind_params(&lease.times, now), &[MessageType::Reply], msg, None) {
+                Ok(res) if res.pd.as_ref().is_some_and(|pd| pd.valid_lifetime > 0) => return Ok(res),
+                Ok(_) => log::info!("Rebind of {}/{} returned no usable prefix", lease.prefix, lease.prefix_len),
+                Err(e) => log::info!("Rebind of {}/{} failed: {}", lease.prefix, lease.prefix_len, e),
+            }
+        }
+        let expected: &[MessageType] = if self.config.rapid_commit {
+            &[MessageType::Advertise, MessageType::Reply]
+        } else {
+            &[MessageType::Advertise]
+        };
+        // ask for the old prefix again, as RFC 8415 section 18.2.1 allows
+        let hint = previous.map(|l| PdHint::prefix(l.prefix, l.prefix_len));
+        let res = self.exchange(RetransParams::SOLICIT, expected, self.solicit_msg(ia_id, hint)?, None)?;
+        if res.msg_type == MessageType::Reply {
+            return Ok(res);
+        }
+        let pd = res.pd.ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, "PD prefix not received"))?;
+        let dest = self.unicast_addr(&res.server_id);
+        let msg = self.request_msg(ia_id, res.server_id, &pd)?;
+        self.exchange(RetransParams::REQUEST, &[MessageType::Reply], msg, dest)
+    }
+
+    /// Rebind parameters for a prefix held from before: retries for at most
+    /// [`Dhcp6ClientConfig::rebind_timeout`] or what is left of `times`.
+    fn rebind_params(&self, times: &LeaseTimes, now: BootInstant) -> RetransParams {
+        let mrd = match times.remaining_valid(now) {
+            Some(valid) => valid.min(self.config.rebind_timeout),
+            None => self.config.rebind_timeout,
+        };
+        RetransParams::REBIND.with_mrd(mrd)
+    }
+
+    /// Releases the prefix in `lease`; the server not answering is not an
+    /// error, as the client stops using the prefix either way.
+    pub fn release_lease(&self, lease: &Dhcp6Lease) -> std::io::Result<()> {
+        let pd = lease.pd(self.config.clock.now());
+        let msg = self.pd_msg(MessageType::Release, lease.ia_id, Some(lease.server_id.clone()), PdHint::from(&pd).to_option())?;
+        let res = self.exchange(RetransParams::RELEASE, &[MessageType::Reply], msg, self.unicast_addr(&lease.server_id));
+        match res {
+            Err(e) if e.kind() == ErrorKind::TimedOut => Ok(()),
+            res => res.map(|_| ()),
+        }
+    }
+
+    /// Sends `msg` to `dest` until a matching reply arrives (RFC 8415
+    /// section 15). Retransmissions keep the transaction ID and only update
+    /// Elapsed Time; packets for other transactions or clients are dropped.
+    /// A server answering UseMulticast is asked again by multicast.
+    fn exchange(&self, params: RetransParams, expected_msg_types: &[MessageType], mut msg: dhcproto::v6::Message, dest: Option<Ipv6Addr>) -> std::io::Result<Dhcp6Response> {
+        let xid = msg.xid_num();
+        let mut res = self.transmit(params, &mut msg, dest, |timeout| self.recv_reply(expected_msg_types, Some(xid), timeout));
+        if dest.is_some() && res.as_ref().is_err_and(|e| e.kind() == ErrorKind::ConnectionRefused) {
+            log::info!("Server asked for multicast, retrying");
+            res = self.transmit(params, &mut msg, None, |timeout| self.recv_reply(expected_msg_types, Some(xid), timeout));
+        }
+        res
+    }
+
+    fn transmit<T, R>(&self, params: RetransParams, msg: &mut dhcproto::v6::Message, dest: Option<Ipv6Addr>, recv: R) -> std::io::Result<T>
+    where
+        R: FnMut(Duration) -> std::io::Result<T>,
+    {
+        clock::retransmit(self.config.clock.as_ref(), params, |elapsed| self.send_msg(msg, elapsed, dest), recv)
+    }
+
+    /// Sends a Confirm for the address in `na` (RFC 8415 section 18.2.3).
+    pub fn confirm_na(&self, elapsed: Duration, na: &NaAddress) -> std::io::Result<()> {
+        let mut msg = self.confirm_msg(na)?;
+        self.send_msg(&mut msg, elapsed, None)
+    }
+
+    fn confirm_msg(&self, na: &NaAddress) -> std::io::Result<dhcproto::v6::Message> {
+        let duid = self.local_duid()?;
+        let mut msg = dhcproto::v6::Message::new(MessageType::Confirm);
+        msg.opts_mut().insert(DhcpOption::ClientId(duid));
+        let mut na_options = DhcpOptions::new();
+        na_options.insert(DhcpOption::IAAddr(IAAddr {
+            addr: na.addr,
+            preferred_life: 0,
+            valid_life: 0,
+            opts: DhcpOptions::new(),
+        }));
+        msg.opts_mut().insert(DhcpOption::IANA(IANA {
+            id: na.ia_id,
+            t1: 0,
+            t2: 0,
+            opts: na_options,
+        }));
+        self.insert_client_options(&mut msg);
         Ok(msg)
     }
 
+    /// Checks after a link change whether the address in `previous` is
+    /// still appropriate for the link, with Confirm. Gives up after
+    /// CNF_MAX_RD, leaving the address in use (RFC 8415 section 18.2.3).
+    pub fn check_na_on_link_change(&self, previous: &Dhcp6Response) -> std::io::Result<BindingCheck> {
+        let na = previous.na.as_ref()
+            .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "No IA_NA address to confirm"))?;
+        if na.lease_times(previous.received_at).is_expired(self.config.clock.now()) {
+            return Ok(BindingCheck::Invalid);
+        }
+        let msg = self.confirm_msg(na)?;
+        match self.exchange(RetransParams::CONFIRM, &[MessageType::Reply], msg, None) {
+            Ok(_) => Ok(BindingCheck::Valid(None)),
+            Err(e) => match StatusError::of(&e) {
+                Some(StatusError(code)) if code.status == Status::NotOnLink => Ok(BindingCheck::Invalid),
+                Some(StatusError(code)) => Ok(BindingCheck::Failed(code.clone())),
+                None if e.kind() == ErrorKind::TimedOut => Ok(BindingCheck::NoReply),
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Checks after a link change whether the delegated prefix in `previous`
+    /// is still valid, with Rebind (RFC 8415 section 18.2.12).
+    pub fn check_pd_on_link_change(&self, ia_id: u32, previous: &Dhcp6Response) -> std::io::Result<BindingCheck> {
+        let pd = previous.pd.as_ref()
+            .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "No delegated prefix to rebind"))?;
+        let now = self.config.clock.now();
+        let times = pd.lease_times(previous.received_at);
+        if times.is_expired(now) {
+            return Ok(BindingCheck::Invalid);
+        }
+        let msg = self.pd_msg(MessageType::Rebind, ia_id, None, PdHint::from(pd).to_option())?;
+        match self.exchange(self.rebind_params(&times, now), &[MessageType::Reply], msg, None) {
+            Ok(res) if res.pd.as_ref().is_some_and(|pd| pd.valid_lifetime > 0) => Ok(BindingCheck::Valid(Some(Box::new(res)))),
+            Ok(_) => Ok(BindingCheck::Invalid),
+            Err(e) => match StatusError::of(&e) {
+                Some(StatusError(code)) => Ok(BindingCheck::Failed(code.clone())),
+                None if e.kind() == ErrorKind::TimedOut => Ok(BindingCheck::NoReply),
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Handles one Reconfigure for `lease` (RFC 8415 section 18.2.11): waits
+    /// for it, then runs the exchange the server asked for. The reply
+    /// carries the reconfigure key with its new replay detection value, to
+    /// be stored with the lease.
+    pub fn handle_reconfigure(&self, lease: &Dhcp6Lease) -> std::io::Result<Dhcp6Response> {
+        self.restore_reconfigure_key(lease);
+        let request = self.recv_reconfigure(&lease.server_id)?;
+        log::info!("Reconfigure: {:?}", request);
+        let now = self.config.clock.now();
+        let pd = lease.pd(now);
+        let mrd = |params: RetransParams| match lease.times.remaining_valid(now) {
+            Some(valid) => params.with_mrd(valid),
+            None => params,
+        };
+        let hint = PdHint::from(&pd).to_option();
+        match request {
+            ReconfigureRequest::Renew => {
+                let msg = self.pd_msg(MessageType::Renew, lease.ia_id, Some(lease.server_id.clone()), hint)?;
+                self.exchange(mrd(RetransParams::RENEW), &[MessageType::Reply], msg, self.unicast_addr(&lease.server_id))
+            },
+            ReconfigureRequest::Rebind => {
+                let msg = self.pd_msg(MessageType::Rebind, lease.ia_id, None, hint)?;
+                self.exchange(mrd(RetransParams::REBIND), &[MessageType::Reply], msg, None)
+            },
+            ReconfigureRequest::InformationRequest => {
+                self.exchange(RetransParams::INFORMATION_REQUEST, &[MessageType::Reply], self.information_request_msg()?, None)
+            },
+        }
+    }
+
+    /// Receives a Reconfigure from the server identified by `server_id` and
+    /// authenticates it with the reconfigure key from an earlier Reply.
+    pub fn recv_reconfigure(&self, server_id: &[u8]) -> std::io::Result<ReconfigureRequest> {
+        let invalid = |msg| std::io::Error::new(ErrorKind::InvalidData, msg);
+        let packet = self.recv_packet(Self::RECV_TIMEOUT)?;
+        let (msg, raw_options) = decode_msg(&packet)?;
+        if msg.msg_type() != MessageType::Reconfigure {
+            return Err(invalid("Unexpected message type"));
+        }
+        let raw_option = |code: u16| raw_options.iter().find(|o| o.code == code).map(|o| o.data.as_slice());
+        if raw_option(u16::from(OptionCode::ServerId)) != Some(server_id) {
+            return Err(invalid("Reconfigure from unknown server"));
+        }
+        if raw_option(u16::from(OptionCode::ClientId)) != Some(self.local_duid()?.as_slice()) {
+            return Err(invalid("client DUID mismatch"));
+        }
+        let request = match raw_option(u16::from(OptionCode::ReconfMsg)) {
+            Some([5]) => ReconfigureRequest::Renew,
+            Some([6]) => ReconfigureRequest::Rebind,
+            Some([11]) => ReconfigureRequest::InformationRequest,
+            _ => return Err(invalid("Invalid Reconfigure Message option")),
+        };
+
+        let mut state = self.state();
+        let key = match &mut state.reconfigure_key {
+            Some((id, key)) if id.as_slice() == server_id => key,
+            _ => return Err(invalid("No reconfigure key")),
+        };
+        let replay = verify_reconfigure(&packet, &key.key)?;
+        if !key.advance(replay) {
+            return Err(invalid("Replayed Reconfigure"));
+        }
+        Ok(request)
+    }
+
+    fn recv_packet(&self, timeout: Duration) -> std::io::Result<Vec<u8>> {
+        self.transport.recv(self.config.clock.as_ref(), timeout)
+    }
+
+    fn recv_msg(&self, timeout: Duration) -> std::io::Result<(dhcproto::v6::Message, Vec<RawOption>)> {
+        decode_msg(&self.recv_packet(timeout)?)
+    }
+
+    /// Receives a message for us: with our Client Identifier and, if given,
+    /// transaction ID `xid`.
+    fn recv_matching(&self, xid: Option<u32>, timeout: Duration) -> std::io::Result<(dhcproto::v6::Message, Vec<RawOption>)> {
+        let (msg, raw_options) = self.recv_msg(timeout)?;
+        if xid.is_some_and(|xid| msg.xid_num() != xid) {
+            return Err(std::io::Error::new(ErrorKind::InvalidData, "Transaction ID mismatch"));
+        }
+        match msg.opts().get(OptionCode::ClientId) {
+            Some(DhcpOption::ClientId(id)) if *id == self.local_duid()? => Ok((msg, raw_options)),
+            Some(_) => Err(std::io::Error::new(ErrorKind::InvalidData, "client DUID mismatch")),
+            None => Err(std::io::Error::new(ErrorKind::InvalidData, "No client DUID")),
+        }
+    }
+
     pub fn recv(&self, expected_msg_type: MessageType) -> std::io::Result<Dhcp6Response> {
-        let msg = self.recv_msg()?;
+        self.recv_any(&[expected_msg_type])
+    }
+
+    /// Receives a message of any of the given types. A Reply to a Solicit,
+    /// i.e. when both Advertise and Reply are expected, is only accepted
+    /// with Rapid Commit.
+    pub fn recv_any(&self, expected_msg_types: &[MessageType]) -> std::io::Result<Dhcp6Response> {
+        self.recv_reply(expected_msg_types, None, Self::RECV_TIMEOUT)
+    }
+
+    /// Like [`Self::recv_any`], also checking the transaction ID if given.
+    fn recv_reply(&self, expected_msg_types: &[MessageType], xid: Option<u32>, timeout: Duration) -> std::io::Result<Dhcp6Response> {
+        let (msg, raw_options) = self.recv_matching(xid, timeout)?;
+        let received_at = ReceivedAt::now(self.config.clock.as_ref());
         let msg_type = msg.msg_type();
-        if msg_type != expected_msg_type {
+        if !expected_msg_types.contains(&msg_type) {
             return Err(std::io::Error::new(ErrorKind::InvalidData, "Unexpected message type"));
         }
+        let rapid_commit = msg.opts().get(OptionCode::RapidCommit).is_some();
+        if msg_type == MessageType::Reply && expected_msg_types.contains(&MessageType::Advertise) && !rapid_commit {
+            return Err(std::io::Error::new(ErrorKind::InvalidData, "Reply to Solicit without Rapid Commit"));
+        }
+        let sent_key = match raw_options.iter().find(|o| o.code == u16::from(OptionCode::Authentication)) {
+            Some(opt) if msg_type == MessageType::Reply => match auth::parse(&opt.data) {
+                Some(AuthValue::Key(key)) => Some(key),
+                _ => None,
+            },
+            _ => None,
+        };
 
         let mut domain_search_list = Vec::new();
         let mut nameserver_addrs = Vec::new();
         let mut sntp_server_addrs = Vec::new();
         let mut sip_server_addrs = Vec::new();
+        let mut ntt_vendor_info = None;
+        let mut ntt_mac_mismatch = None;
+        let mut aftr_name = None;
+        let mut map_e = None;
+        let mut map_t = None;
+        let mut lw4o6 = None;
+        let mut dhcp4o6_servers = None;
         let mut client_id = None;
         let mut server_id = None;
         let mut t1: u32 = 0;
@@ -203,14 +1035,13 @@ impl Dhcp6Client {
         let mut preferred_lifetime: u32 = 0;
         let mut prefix = None;
         let mut prefix_len = None;
+        let mut excluded = None;
+        let mut na = None;
+        let mut server_unicast = None;
         for opt in msg.opts().iter() {
             let opt = opt.to_owned();
             match opt {
                 DhcpOption::ClientId(id) => {
-                    let duid = self.local_duid()?;
-                    if id != duid {
-                        return Err(std::io::Error::new(ErrorKind::InvalidData, "client DUID mismatch"));
-                    }
                     client_id = Some(id);
                 },
 
@@ -218,11 +1049,19 @@ impl Dhcp6Client {
                     server_id = Some(id);
                 },
 
+                DhcpOption::ServerUnicast(addr) => {
+                    server_unicast = Some(addr);
+                },
+
                 DhcpOption::StatusCode(code) => {
                     match code.status {
                         Status::Success => {},
+                        Status::UseMulticast => {
+                            self.state().server_unicast = None;
+                            return Err(std::io::Error::new(ErrorKind::ConnectionRefused, "Server requires multicast"));
+                        },
                         _ => {
-                            return Err(std::io::Error::new(ErrorKind::ConnectionAborted, "DHCP error"));
+                            return Err(std::io::Error::new(ErrorKind::ConnectionAborted, StatusError(code)));
                         },
                     }
                 },
@@ -238,23 +1077,70 @@ impl Dhcp6Client {
                 },
 
                 DhcpOption::IAPD(pd) => {
+                    let expected_ia_id = self.state().ia_id;
+                    if expected_ia_id.is_some_and(|id| id != pd.id) {
+                        return Err(std::io::Error::new(ErrorKind::InvalidData, "IA_PD IAID mismatch"));
+                    }
                     t1 = pd.t1;
                     t2 = pd.t2;
 
                     for opt in pd.opts.iter() {
-                        let opt = opt.to_owned();
-                        match opt {
-                            DhcpOption::IAPrefix(pd_prefix) => {
-                                valid_lifetime = pd_prefix.valid_lifetime;
-                                preferred_lifetime = pd_prefix.preferred_lifetime;
-                                prefix = Some(pd_prefix.prefix_ip);
-                                prefix_len = Some(pd_prefix.prefix_len);
-                            },
-                            _ => {},
+                        if let DhcpOption::IAPrefix(pd_prefix) = opt {
+                            valid_lifetime = pd_prefix.valid_lifetime;
+                            preferred_lifetime = pd_prefix.preferred_lifetime;
+                            prefix = Some(pd_prefix.prefix_ip);
+                            prefix_len = Some(pd_prefix.prefix_len);
+                            excluded = None;
+                            let Ok(net) = Ipv6Net::new(pd_prefix.prefix_ip, pd_prefix.prefix_len) else {
+                                continue;
+                            };
+                            for opt in pd_prefix.opts.iter() {
+                                let DhcpOption::Unknown(opt) = opt else { continue };
+                                if opt.code() != OptionCode::PdExclude {
+                                    continue;
+                                }
+                                match decode_pd_exclude(net.trunc(), opt.data()) {
+                                    Ok(ex) => excluded = Some(ex),
+                                    Err(e) => log::warn!("{}", e),
+                                }
+                            }
+                        }
+                    }
+                },
+
+                DhcpOption::IANA(ia_na) => {
+                    for opt in ia_na.opts.iter() {
+                        if let DhcpOption::IAAddr(ia_addr) = opt {
+                            na = Some(NaAddress {
+                                ia_id: ia_na.id,
+                                addr: ia_addr.addr,
+                                preferred_lifetime: ia_addr.preferred_life,
+                                valid_lifetime: ia_addr.valid_life,
+                                t1: ia_na.t1,
+                                t2: ia_na.t2,
+                            });
                         }
                     }
                 },
 
+                DhcpOption::VendorOpts(ref vendor_opts) => {
+                    if !self.config.profile.decodes_vendor(vendor_opts.num) || vendor_opts.num != ntt::ENTERPRISE_NUMBER {
+                        continue;
+                    }
+                    match NttVendorInfo::decode_dhcp6(&option_payload(&opt)?) {
+                        Ok(info) => {
+                            if let Err(e) = info.verify_mac(self.local_if_mac) {
+                                log::warn!("{}: {:02x?}", e, info.mac_addr);
+                                ntt_mac_mismatch = info.mac_addr;
+                            }
+                            ntt_vendor_info = Some(info);
+                        },
+                        Err(e) => {
+                            log::warn!("Invalid NTT vendor option: {}", e);
+                        },
+                    }
+                },
+
                 DhcpOption::Unknown(opt) => {
                     let code = opt.code();
                     let (_, data) = opt.into_parts();
@@ -283,7 +1169,29 @@ impl Dhcp6Client {
                                 let addr: Ipv6Addr = addr.into();
                                 sntp_server_addrs.push(addr);
                             }
-                        }
+                        },
+                        OptionCode::Dhcp4ODhcp6Server => match dhcp4o6::decode_servers(&data) {
+                            Ok(servers) => dhcp4o6_servers = Some(servers),
+                            Err(e) => log::warn!("{}", e),
+                        },
+                        OptionCode::AftrName => match softwire::decode_aftr_name(&data) {
+                            Ok(name) => aftr_name = Some(name),
+                            Err(e) => log::warn!("Invalid AFTR-Name option: {}", e),
+                        },
+                        OptionCode::S46ContMape | OptionCode::S46ContMapt | OptionCode::S46ContLw => {
+                            let container = match S46Container::decode(&data) {
+                                Ok(container) => container,
+                                Err(e) => {
+                                    log::warn!("Invalid softwire container {:?}: {}", code, e);
+                                    continue;
+                                },
+                            };
+                            match code {
+                                OptionCode::S46ContMape => map_e = Some(container),
+                                OptionCode::S46ContMapt => map_t = Some(container),
+                                _ => lw4o6 = Some(container),
+                            }
+                        },
                         _ => {},
                     }
                 },
@@ -292,28 +1200,58 @@ impl Dhcp6Client {
             }
         }
 
-        let pd;
-        if prefix.is_none() || prefix_len.is_none() {
-            pd = None;
-        } else {
-            pd = Some(PdPrefix {
-                prefix: prefix.unwrap(),
-                prefix_len: prefix_len.unwrap(),
+        let pd = match (prefix, prefix_len) {
+            (Some(prefix), Some(prefix_len)) => Some(PdPrefix {
+                prefix,
+                prefix_len,
                 preferred_lifetime,
                 valid_lifetime,
                 t1,
                 t2,
-            });
+                excluded,
+            }),
+            _ => None,
+        };
+
+        let client_id = client_id.ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, "No client DUID"))?;
+        let server_id = server_id.ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, "No server DUID"))?;
+        if let Some(addr) = server_unicast {
+            self.state().server_unicast = Some((server_id.clone(), addr));
         }
+        if na.is_some() {
+            self.state().na = na.clone();
+        }
+        let reconfigure_key = match sent_key {
+            Some(key) => {
+                self.state().reconfigure_key = Some((server_id.clone(), key));
+                Some(key)
+            },
+            None => self.reconfigure_key(&server_id),
+        };
 
         let res = Dhcp6Response {
-            client_id: client_id.unwrap(),
-            server_id: server_id.unwrap(),
+            msg_type,
+            client_id,
+            server_id,
             pd,
+            na,
             nameserver_addrs,
             domain_search_list,
             sip_server_addrs,
             sntp_server_addrs,
+            ntt_vendor_info,
+            ntt_mac_mismatch,
+            aftr_name,
+            map_e,
+            map_t,
+            lw4o6,
+            dhcp4o6_servers,
+            received_at,
+            rapid_commit,
+            reconfigure_key,
+            server_unicast,
+            custom_options: self.config.decoders.decode_dhcp6(&raw_options),
+            raw_options,
         };
         Ok(res)
     }
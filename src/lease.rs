@@ -0,0 +1,543 @@
+
+//! Lease records with absolute deadlines, and their persistence.
+
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use dhcproto::v4::MessageType;
+use ipnet::Ipv6Net;
+
+use crate::auth::AuthKey;
+use crate::clock::{BootInstant, Clock, ClockJump, JumpDetector, SystemClock};
+use crate::ipv4::Dhcp4Response;
+use crate::ipv6::{Dhcp6Response, PdPrefix};
+use crate::persist::{from_hex, invalid, parse_kv, to_hex, write_atomic};
+
+/// Lifetime value meaning infinity (RFC 2131 section 3.3, RFC 8415 section 7.7).
+pub const INFINITE_LIFETIME: u32 = 0xffff_ffff;
+
+/// When a reply was received, on both the boot-time and the wall clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReceivedAt {
+    pub monotonic: BootInstant,
+    pub wall: SystemTime,
+}
+
+impl ReceivedAt {
+    pub fn now(clock: &dyn Clock) -> Self {
+        Self {
+            monotonic: clock.now(),
+            wall: clock.wall(),
+        }
+    }
+}
+
+/// Where a lease stands relative to its timers (RFC 2131 section 4.4,
+/// RFC 8415 section 18.2.4).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaseState {
+    Bound,
+    /// Past T1: renew with the server that granted the lease.
+    Renewing,
+    /// Past T2: rebind with any server.
+    Rebinding,
+    Expired,
+}
+
+/// Lease timers counted from the time the reply was received.
+/// `None` stands for an infinite duration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeaseTimes {
+    pub received_at: ReceivedAt,
+    pub t1: Option<Duration>,
+    pub t2: Option<Duration>,
+    pub preferred: Option<Duration>,
+    pub valid: Option<Duration>,
+}
+
+fn lifetime(secs: u32) -> Option<Duration> {
+    match secs {
+        INFINITE_LIFETIME => None,
+        _ => Some(Duration::from_secs(secs as u64)),
+    }
+}
+
+fn lifetime_secs(d: Option<Duration>) -> u32 {
+    match d {
+        None => INFINITE_LIFETIME,
+        Some(d) => d.as_secs().min(INFINITE_LIFETIME as u64 - 1) as u32,
+    }
+}
+
+impl LeaseTimes {
+    /// Longest single sleep in [`Self::wait`].
+    pub const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+    /// Interprets lifetimes and T1/T2 in seconds as sent by the server.
+    /// A T1 or T2 of 0 means the server left the choice to the client, in
+    /// which case 0.5 and 0.875 times the preferred lifetime are used.
+    pub fn new(received_at: ReceivedAt, preferred: u32, valid: u32, t1: u32, t2: u32) -> Self {
+        let preferred = lifetime(preferred);
+        let valid = lifetime(valid);
+        let default_t1 = preferred.map(|p| p / 2);
+        let default_t2 = preferred.map(|p| p * 7 / 8);
+        let (t1, t2) = match (t1, t2) {
+            (0, 0) => (default_t1, default_t2),
+            (0, t2) => match (default_t1, lifetime(t2)) {
+                (Some(d), Some(t2)) => (Some(d.min(t2)), Some(t2)),
+                (d, t2) => (d.or(t2), t2),
+            },
+            (t1, 0) => match (lifetime(t1), default_t2) {
+                (Some(t1), Some(d)) => (Some(t1), Some(d.max(t1))),
+                (t1, _) => (t1, None),
+            },
+            // RFC 8415 section 21.21: T1 > T2 is invalid
+            (t1, t2) if t1 > t2 => (default_t1, default_t2),
+            (t1, t2) => (lifetime(t1), lifetime(t2)),
+        };
+        Self {
+            received_at,
+            t1,
+            t2,
+            preferred,
+            valid,
+        }
+    }
+
+    fn deadline(&self, d: Option<Duration>) -> Option<BootInstant> {
+        d.map(|d| self.received_at.monotonic + d)
+    }
+
+    fn remaining(&self, d: Option<Duration>, now: BootInstant) -> Option<Duration> {
+        d.map(|d| d.saturating_sub(now.saturating_duration_since(self.received_at.monotonic)))
+    }
+
+    /// When to renew (T1); `None` if never.
+    pub fn renew_at(&self) -> Option<BootInstant> {
+        self.deadline(self.t1)
+    }
+
+    /// When to rebind (T2); `None` if never.
+    pub fn rebind_at(&self) -> Option<BootInstant> {
+        self.deadline(self.t2)
+    }
+
+    pub fn preferred_until(&self) -> Option<BootInstant> {
+        self.deadline(self.preferred)
+    }
+
+    pub fn expires_at(&self) -> Option<BootInstant> {
+        self.deadline(self.valid)
+    }
+
+    pub fn remaining_preferred(&self, now: BootInstant) -> Option<Duration> {
+        self.remaining(self.preferred, now)
+    }
+
+    pub fn remaining_valid(&self, now: BootInstant) -> Option<Duration> {
+        self.remaining(self.valid, now)
+    }
+
+    pub fn is_expired(&self, now: BootInstant) -> bool {
+        self.remaining_valid(now).is_some_and(|d| d.is_zero())
+    }
+
+    pub fn state(&self, now: BootInstant) -> LeaseState {
+        let passed = |t: Option<BootInstant>| t.is_some_and(|t| now >= t);
+        if self.is_expired(now) {
+            LeaseState::Expired
+        } else if passed(self.rebind_at()) {
+            LeaseState::Rebinding
+        } else if passed(self.renew_at()) {
+            LeaseState::Renewing
+        } else {
+            LeaseState::Bound
+        }
+    }
+
+    /// The next deadline after `now` at which the state changes, if any.
+    pub fn next_deadline(&self, now: BootInstant) -> Option<BootInstant> {
+        [self.renew_at(), self.rebind_at(), self.expires_at()].into_iter()
+            .flatten()
+            .filter(|t| *t > now)
+            .min()
+    }
+
+    /// Sleeps until the state changes and returns the new state. Sleeps in
+    /// steps of at most [`Self::MAX_SLEEP`], so that deadlines passed while
+    /// the system was suspended are acted upon right after resuming. Returns
+    /// at once if no deadline is left.
+    ///
+    /// A wall clock step moves `received_at.wall` along with it, so that the
+    /// lease keeps its deadlines when stored again afterwards.
+    pub fn wait(&mut self, clock: &dyn Clock) -> LeaseState {
+        let mut detector = JumpDetector::new(clock, JumpDetector::DEFAULT_THRESHOLD);
+        let initial = self.state(clock.now());
+        loop {
+            let now = clock.now();
+            let state = self.state(now);
+            if state != initial {
+                return state;
+            }
+            let Some(deadline) = self.next_deadline(now) else {
+                return state;
+            };
+            let step = (deadline - now).min(Self::MAX_SLEEP);
+            clock.sleep(step);
+            match detector.check(clock, step) {
+                Some(ClockJump::Suspended(d)) => log::info!("Resumed after {:?}, rechecking lease timers", d),
+                Some(ClockJump::WallStepped { forward, by }) => {
+                    log::info!("Wall clock stepped {} by {:?}", if forward { "forward" } else { "back" }, by);
+                    let wall = self.received_at.wall;
+                    self.received_at.wall = if forward {
+                        wall + by
+                    } else {
+                        wall.checked_sub(by).unwrap_or(UNIX_EPOCH)
+                    };
+                },
+                None => {},
+            }
+        }
+    }
+
+    fn encode_into(&self, s: &mut String) {
+        let secs = |d: Option<Duration>| match d {
+            None => "infinite".to_string(),
+            Some(d) => d.as_secs().to_string(),
+        };
+        let received = self.received_at.wall.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+        s.push_str(&format!("received_at={}\n", received.as_secs()));
+        s.push_str(&format!("t1={}\n", secs(self.t1)));
+        s.push_str(&format!("t2={}\n", secs(self.t2)));
+        s.push_str(&format!("preferred={}\n", secs(self.preferred)));
+        s.push_str(&format!("valid={}\n", secs(self.valid)));
+    }
+
+    /// Reads timers written by `encode_into`. Monotonic time does not
+    /// survive a reboot, so they are rebased onto the current time and
+    /// shortened by the wall-clock time passed since they were stored.
+    /// If the wall clock is behind the stored time, the age is unknown and
+    /// T1 and T2 are taken as passed, so the lease is rebound before use.
+    fn decode(kv: &[(&str, &str)], clock: &dyn Clock) -> std::io::Result<Self> {
+        let get = |key: &str| {
+            kv.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+                .ok_or_else(|| invalid("Incomplete lease timers"))
+        };
+        let secs = |key: &str| -> std::io::Result<Option<Duration>> {
+            match get(key)? {
+                "infinite" => Ok(None),
+                v => Ok(Some(Duration::from_secs(parse_value(v)?))),
+            }
+        };
+        let received = UNIX_EPOCH + Duration::from_secs(parse_value(get("received_at")?)?);
+        let now = ReceivedAt::now(clock);
+        let Ok(age) = now.wall.duration_since(received) else {
+            log::warn!("Lease received in the future, wall clock is behind");
+            return Ok(Self {
+                received_at: now,
+                t1: Some(Duration::ZERO),
+                t2: Some(Duration::ZERO),
+                preferred: secs("preferred")?,
+                valid: secs("valid")?,
+            });
+        };
+        let rebase = |d: Option<Duration>| d.map(|d| d.saturating_sub(age));
+        Ok(Self {
+            received_at: now,
+            t1: rebase(secs("t1")?),
+            t2: rebase(secs("t2")?),
+            preferred: rebase(secs("preferred")?),
+            valid: rebase(secs("valid")?),
+        })
+    }
+}
+
+/// A DHCPv4 lease.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dhcp4Lease {
+    pub client_addr: Ipv4Addr,
+    pub server_addr: Ipv4Addr,
+    pub subnet_mask: Option<Ipv4Addr>,
+    pub router_addrs: Vec<Ipv4Addr>,
+    /// Needed to authenticate DHCPFORCERENEW messages after a restart.
+    pub forcerenew_nonce: Option<AuthKey>,
+    pub times: LeaseTimes,
+}
+
+/// A delegated prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dhcp6Lease {
+    pub client_id: Vec<u8>,
+    pub server_id: Vec<u8>,
+    pub ia_id: u32,
+    pub prefix: Ipv6Addr,
+    pub prefix_len: u8,
+    pub excluded: Option<Ipv6Net>,
+    pub nameserver_addrs: Vec<Ipv6Addr>,
+    pub domain_search_list: Vec<String>,
+    /// Needed to authenticate Reconfigure messages after a restart.
+    pub reconfigure_key: Option<AuthKey>,
+    pub times: LeaseTimes,
+}
+
+fn parse_value<T: FromStr>(s: &str) -> std::io::Result<T> {
+    s.parse().map_err(|_| invalid("Invalid lease value"))
+}
+
+fn parse_list<T: FromStr>(s: &str) -> std::io::Result<Vec<T>> {
+    s.split(',').filter(|v| !v.is_empty()).map(parse_value).collect()
+}
+
+fn join_list<T: ToString>(items: &[T]) -> String {
+    items.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn encode_auth_key(s: &mut String, name: &str, key: Option<&AuthKey>) {
+    if let Some(key) = key {
+        s.push_str(&format!("{}={}\n", name, to_hex(&key.key)));
+        s.push_str(&format!("replay_counter={}\n", key.replay_counter));
+    }
+}
+
+/// Reads a key written by `encode_auth_key`; both lines or neither must
+/// be present.
+fn decode_auth_key(kv: &[(&str, &str)], name: &str) -> std::io::Result<Option<AuthKey>> {
+    let get = |key: &str| kv.iter().find(|(k, _)| *k == key).map(|(_, v)| *v);
+    match (get(name), get("replay_counter")) {
+        (Some(key), Some(replay_counter)) => Ok(Some(AuthKey {
+            key: from_hex(key)?.try_into().map_err(|_| invalid("Invalid authentication key"))?,
+            replay_counter: parse_value(replay_counter)?,
+        })),
+        (None, None) => Ok(None),
+        _ => Err(invalid("Incomplete authentication key")),
+    }
+}
+
+impl Dhcp4Lease {
+    /// Builds a lease from a DHCPACK; any other message is rejected.
+    pub fn from_response(res: &Dhcp4Response) -> std::io::Result<Self> {
+        if res.msg_type != MessageType::Ack {
+            return Err(invalid("Not a DHCPACK for a lease"));
+        }
+        let client_addr = res.client_addr.ok_or_else(|| invalid("No client address in response"))?;
+        let server_addr = res.server_addr.ok_or_else(|| invalid("No server address in response"))?;
+        Ok(Self {
+            client_addr,
+            server_addr,
+            subnet_mask: res.subnet_mask,
+            router_addrs: res.router_addrs.clone(),
+            forcerenew_nonce: res.forcerenew_nonce,
+            times: res.lease_times(),
+        })
+    }
+
+    pub fn is_expired(&self, now: BootInstant) -> bool {
+        self.times.is_expired(now)
+    }
+
+    pub fn encode(&self) -> String {
+        let mut s = String::new();
+        s.push_str(&format!("client_addr={}\n", self.client_addr));
+        s.push_str(&format!("server_addr={}\n", self.server_addr));
+        if let Some(mask) = self.subnet_mask {
+            s.push_str(&format!("subnet_mask={}\n", mask));
+        }
+        s.push_str(&format!("router_addrs={}\n", join_list(&self.router_addrs)));
+        encode_auth_key(&mut s, "forcerenew_nonce", self.forcerenew_nonce.as_ref());
+        self.times.encode_into(&mut s);
+        s
+    }
+
+    pub fn decode(s: &str, clock: &dyn Clock) -> std::io::Result<Self> {
+        let kv = parse_kv(s);
+        let mut client_addr = None;
+        let mut server_addr = None;
+        let mut subnet_mask = None;
+        let mut router_addrs = Vec::new();
+        for (key, value) in kv.iter().copied() {
+            match key {
+                "client_addr" => client_addr = Some(parse_value(value)?),
+                "server_addr" => server_addr = Some(parse_value(value)?),
+                "subnet_mask" => subnet_mask = Some(parse_value(value)?),
+                "router_addrs" => router_addrs = parse_list(value)?,
+                _ => {},
+            }
+        }
+        let missing = || invalid("Incomplete DHCPv4 lease");
+        Ok(Self {
+            client_addr: client_addr.ok_or_else(missing)?,
+            server_addr: server_addr.ok_or_else(missing)?,
+            subnet_mask,
+            router_addrs,
+            forcerenew_nonce: decode_auth_key(&kv, "forcerenew_nonce")?,
+            times: LeaseTimes::decode(&kv, clock)?,
+        })
+    }
+}
+
+impl Dhcp6Lease {
+    /// Builds a lease from a Reply; any other message is rejected.
+    pub fn from_response(res: &Dhcp6Response, ia_id: u32) -> std::io::Result<Self> {
+        if res.msg_type != dhcproto::v6::MessageType::Reply {
+            return Err(invalid("Not a Reply"));
+        }
+        let pd = res.pd.as_ref().ok_or_else(|| invalid("No delegated prefix in response"))?;
+        Ok(Self {
+            client_id: res.client_id.clone(),
+            server_id: res.server_id.clone(),
+            ia_id,
+            prefix: pd.prefix,
+            prefix_len: pd.prefix_len,
+            excluded: pd.excluded,
+            nameserver_addrs: res.nameserver_addrs.clone(),
+            domain_search_list: res.domain_search_list.clone(),
+            reconfigure_key: res.reconfigure_key,
+            times: pd.lease_times(res.received_at),
+        })
+    }
+
+    pub fn is_expired(&self, now: BootInstant) -> bool {
+        self.times.is_expired(now)
+    }
+
+    /// The delegated prefix with lifetimes remaining at `now`.
+    pub fn pd(&self, now: BootInstant) -> PdPrefix {
+        let times = &self.times;
+        PdPrefix {
+            prefix: self.prefix,
+            prefix_len: self.prefix_len,
+            preferred_lifetime: lifetime_secs(times.remaining_preferred(now)),
+            valid_lifetime: lifetime_secs(times.remaining_valid(now)),
+            t1: lifetime_secs(times.remaining(times.t1, now)),
+            t2: lifetime_secs(times.remaining(times.t2, now)),
+            excluded: self.excluded,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let mut s = String::new();
+        s.push_str(&format!("client_id={}\n", to_hex(&self.client_id)));
+        s.push_str(&format!("server_id={}\n", to_hex(&self.server_id)));
+        s.push_str(&format!("ia_id={:08x}\n", self.ia_id));
+        s.push_str(&format!("prefix={}/{}\n", self.prefix, self.prefix_len));
+        if let Some(excluded) = self.excluded {
+            s.push_str(&format!("excluded={}\n", excluded));
+        }
+        s.push_str(&format!("nameserver_addrs={}\n", join_list(&self.nameserver_addrs)));
+        s.push_str(&format!("domain_search_list={}\n", self.domain_search_list.join(",")));
+        encode_auth_key(&mut s, "reconfigure_key", self.reconfigure_key.as_ref());
+        self.times.encode_into(&mut s);
+        s
+    }
+
+    pub fn decode(s: &str, clock: &dyn Clock) -> std::io::Result<Self> {
+        let kv = parse_kv(s);
+        let mut client_id = None;
+        let mut server_id = None;
+        let mut ia_id = None;
+        let mut prefix = None;
+        let mut excluded = None;
+        let mut nameserver_addrs = Vec::new();
+        let mut domain_search_list = Vec::new();
+        for (key, value) in kv.iter().copied() {
+            match key {
+                "client_id" => client_id = Some(from_hex(value)?),
+                "server_id" => server_id = Some(from_hex(value)?),
+                "ia_id" => ia_id = Some(u32::from_str_radix(value, 16).map_err(|_| invalid("Invalid IAID"))?),
+                "prefix" => {
+                    let (addr, len) = value.split_once('/').ok_or_else(|| invalid("Invalid prefix"))?;
+                    prefix = Some((parse_value(addr)?, parse_value(len)?));
+                },
+                "excluded" => excluded = Some(parse_value(value)?),
+                "nameserver_addrs" => nameserver_addrs = parse_list(value)?,
+                "domain_search_list" => domain_search_list = parse_list(value)?,
+                _ => {},
+            }
+        }
+        let missing = || invalid("Incomplete DHCPv6 lease");
+        let (prefix, prefix_len) = prefix.ok_or_else(missing)?;
+        Ok(Self {
+            client_id: client_id.ok_or_else(missing)?,
+            server_id: server_id.ok_or_else(missing)?,
+            ia_id: ia_id.ok_or_else(missing)?,
+            prefix,
+            prefix_len,
+            excluded,
+            nameserver_addrs,
+            domain_search_list,
+            reconfigure_key: decode_auth_key(&kv, "reconfigure_key")?,
+            times: LeaseTimes::decode(&kv, clock)?,
+        })
+    }
+}
+
+/// Stores the leases of one interface as files in a directory.
+#[derive(Debug, Clone)]
+pub struct LeaseStore {
+    dir: PathBuf,
+    if_name: String,
+    clock: Arc<dyn Clock>,
+}
+
+impl LeaseStore {
+    pub fn new(dir: &Path, if_name: &str) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            if_name: if_name.to_string(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Uses `clock` to rebase the timers of loaded leases.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn path(&self, suffix: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", self.if_name, suffix))
+    }
+
+    fn load<T>(&self, suffix: &str, decode: fn(&str, &dyn Clock) -> std::io::Result<T>) -> std::io::Result<Option<T>> {
+        match std::fs::read_to_string(self.path(suffix)) {
+            Ok(contents) => decode(&contents, self.clock.as_ref()).map(Some),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn remove(&self, suffix: &str) -> std::io::Result<()> {
+        match std::fs::remove_file(self.path(suffix)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn load_dhcp4(&self) -> std::io::Result<Option<Dhcp4Lease>> {
+        self.load("dhcp4.lease", Dhcp4Lease::decode)
+    }
+
+    pub fn store_dhcp4(&self, lease: &Dhcp4Lease) -> std::io::Result<()> {
+        write_atomic(&self.path("dhcp4.lease"), lease.encode().as_bytes())
+    }
+
+    pub fn remove_dhcp4(&self) -> std::io::Result<()> {
+        self.remove("dhcp4.lease")
+    }
+
+    pub fn load_dhcp6(&self) -> std::io::Result<Option<Dhcp6Lease>> {
+        self.load("dhcp6.lease", Dhcp6Lease::decode)
+    }
+
+    pub fn store_dhcp6(&self, lease: &Dhcp6Lease) -> std::io::Result<()> {
+        write_atomic(&self.path("dhcp6.lease"), lease.encode().as_bytes())
+    }
+
+    pub fn remove_dhcp6(&self) -> std::io::Result<()> {
+        self.remove("dhcp6.lease")
+    }
+}
@@ -1,3 +1,18 @@
 
+pub mod auth;
+pub mod clock;
+pub mod dhcp4o6;
+pub mod identity;
 pub mod ipv4;
 pub mod ipv6;
+pub mod lease;
+pub mod map;
+mod md5;
+pub mod ntt;
+pub mod options;
+pub mod prefix_plan;
+pub mod profile;
+pub mod softwire;
+pub mod transport;
+
+mod persist;
@@ -0,0 +1,146 @@
+//! MAP address and port mapping (RFC 7597 section 5): derives the CE's
+//! IPv4 address, port set and MAP IPv6 address from a delegated prefix,
+//! and the MAP IPv6 address a Forwarding Mapping Rule sends to.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::ops::RangeInclusive;
+
+use ipnet::{Ipv4Net, Ipv6Net};
+
+use crate::ipv6::PdPrefix;
+use crate::persist::invalid;
+use crate::softwire::S46Rule;
+
+/// PSID offset when the rule carries no port parameters.
+pub const DEFAULT_PSID_OFFSET: u8 = 6;
+
+/// The result of applying a Basic Mapping Rule to an end-user prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapConfig {
+    pub rule: S46Rule,
+    /// The shared IPv4 address as a /32, or the IPv4 prefix when the
+    /// EA bits do not cover the whole address.
+    pub ipv4: Ipv4Net,
+    pub psid: u16,
+    pub psid_len: u8,
+    pub psid_offset: u8,
+    /// The MAP IPv6 address, in the first /64 of the delegated prefix.
+    pub ce_addr: Ipv6Addr,
+}
+
+/// Returns the rule with the longest IPv6 prefix covering `prefix`.
+pub fn select_rule(rules: &[S46Rule], prefix: Ipv6Net) -> Option<&S46Rule> {
+    rules.iter()
+        .filter(|r| r.ipv6_prefix.contains(&prefix) && prefix.prefix_len() >= r.ipv6_prefix.prefix_len() + r.ea_len)
+        .max_by_key(|r| r.ipv6_prefix.prefix_len())
+}
+
+impl MapConfig {
+    /// Applies the matching rule in `rules` to a delegated prefix.
+    pub fn from_pd(rules: &[S46Rule], pd: &PdPrefix) -> std::io::Result<Self> {
+        let prefix = Ipv6Net::new(pd.prefix, pd.prefix_len)
+            .map_err(|_| invalid("Invalid delegated prefix length"))?
+            .trunc();
+        Self::from_prefix(rules, prefix)
+    }
+
+    pub fn from_prefix(rules: &[S46Rule], prefix: Ipv6Net) -> std::io::Result<Self> {
+        let rule = select_rule(rules, prefix).ok_or_else(|| invalid("No MAP rule matches the prefix"))?;
+        Self::new(rule, prefix)
+    }
+
+    /// Applies `rule` to an end-user prefix it covers.
+    pub fn new(rule: &S46Rule, prefix: Ipv6Net) -> std::io::Result<Self> {
+        let r6 = rule.ipv6_prefix.prefix_len();
+        let r4 = rule.ipv4_prefix.prefix_len();
+        let o = rule.ea_len;
+        let end_user_len = r6 + o;
+        if !rule.ipv6_prefix.contains(&prefix) || prefix.prefix_len() < end_user_len {
+            return Err(invalid("MAP rule does not cover the prefix"));
+        }
+        if prefix.prefix_len() > 64 {
+            return Err(invalid("Delegated prefix longer than /64"));
+        }
+
+        let bits = u128::from(prefix.addr());
+        let ea = if o == 0 { 0 } else { ((bits << r6) >> (128 - o as u32)) as u64 };
+
+        // p bits of the IPv4 suffix, then q bits of PSID
+        let p = (32 - r4).min(o);
+        let q = o - p;
+        if q > 16 {
+            return Err(invalid("PSID longer than 16 bits"));
+        }
+        let suffix = (ea >> q) as u32;
+        let (psid, psid_len) = match rule.port_params {
+            // when the EA bits hold none of the PSID (lw4o6, 1:1, or
+            // only an IPv4 suffix) only the rule can give it
+            Some(pp) if q == 0 => (pp.psid, pp.psid_len),
+            _ => ((ea & ((1u64 << q) - 1)) as u16, q),
+        };
+        let v4 = u32::from(rule.ipv4_prefix.addr()) | if p == 0 { 0 } else { suffix << (32 - r4 - p) };
+        let ipv4 = Ipv4Net::new(Ipv4Addr::from(v4), r4 + p).unwrap();
+
+        let psid_offset = rule.port_params.map_or(DEFAULT_PSID_OFFSET, |pp| pp.offset);
+        if psid_offset + psid_len > 16 {
+            return Err(invalid("PSID offset too long"));
+        }
+
+        // the first subnet of the delegated prefix, then an interface ID of
+        // 16 bits of zero, the IPv4 address and the PSID
+        let iid = ((v4 as u128) << 16) | psid as u128;
+        let ce_addr = Ipv6Addr::from(u128::from(prefix.trunc().addr()) | iid);
+
+        Ok(Self {
+            rule: rule.clone(),
+            ipv4,
+            psid,
+            psid_len,
+            psid_offset,
+            ce_addr,
+        })
+    }
+
+    /// Applies a Forwarding Mapping Rule: the configuration of the CE
+    /// holding `addr` and `port`, whose `ce_addr` is where packets for
+    /// them go (RFC 7597 section 5.3).
+    pub fn for_destination(rule: &S46Rule, addr: Ipv4Addr, port: u16) -> std::io::Result<Self> {
+        if !rule.ipv4_prefix.contains(&addr) {
+            return Err(invalid("MAP rule does not cover the address"));
+        }
+        let r6 = rule.ipv6_prefix.prefix_len();
+        let r4 = rule.ipv4_prefix.prefix_len();
+        let o = rule.ea_len;
+        let p = (32 - r4).min(o);
+        let q = o - p;
+        let a = rule.port_params.map_or(DEFAULT_PSID_OFFSET, |pp| pp.offset);
+        if q > 16 || a + q > 16 {
+            return Err(invalid("PSID offset too long"));
+        }
+        let suffix = if p == 0 { 0 } else { ((u32::from(addr) << r4) >> (32 - p)) as u64 };
+        let psid = if q == 0 { 0 } else { (port as u64 >> (16 - a - q)) & ((1 << q) - 1) };
+        let ea = ((suffix << q) | psid) as u128;
+        let prefix_addr = u128::from(rule.ipv6_prefix.network()) | if o == 0 { 0 } else { ea << (128 - r6 - o) as u32 };
+        let prefix = Ipv6Net::new(Ipv6Addr::from(prefix_addr), r6 + o)
+            .map_err(|_| invalid("MAP rule too long"))?;
+        Self::new(rule, prefix)
+    }
+
+    /// The ports usable by this CE, excluding the well-known ones the
+    /// PSID offset reserves. Covers all ports when nothing is shared.
+    pub fn port_ranges(&self) -> Vec<RangeInclusive<u16>> {
+        let a = self.psid_offset as u32;
+        let k = self.psid_len as u32;
+        if k == 0 {
+            return vec![0..=u16::MAX];
+        }
+        let m = 16 - a - k;
+        let first = if a == 0 { 0 } else { 1 };
+        (first..1u32 << a)
+            .map(|i| {
+                let start = (i << (16 - a)) | ((self.psid as u32) << m);
+                start as u16..=(start + (1 << m) - 1) as u16
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,273 @@
+
+//! Codec for the NTT (enterprise number 210) vendor options.
+//!
+//! DHCPv4 carries them in the V-I Vendor Class (124) and V-I
+//! Vendor-Specific Information (125) options of RFC 3925; DHCPv6 uses
+//! the Vendor Class (16) and Vendor-specific Information (17) options.
+//! The suboption codes are the same in both.
+
+use crate::persist::invalid;
+
+pub const ENTERPRISE_NUMBER: u32 = 210;
+
+pub const SUBOPT_MAC_ADDR: u16 = 201;
+pub const SUBOPT_MAIN_NUMBER: u16 = 202;
+pub const SUBOPT_ADD_NUMBER: u16 = 203;
+pub const SUBOPT_SIP_DOMAIN: u16 = 204;
+
+/// Vendor-specific information sent by NGN servers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NttVendorInfo {
+    /// The MAC address the server believes it is talking to (suboption 201).
+    pub mac_addr: Option<[u8; 6]>,
+    pub main_number: Option<String>,
+    pub add_numbers: Vec<String>,
+    pub sip_domain: Option<String>,
+}
+
+fn check_enterprise(data: &[u8]) -> std::io::Result<&[u8]> {
+    if data.len() < 4 {
+        return Err(invalid("Truncated vendor option"));
+    }
+    let entnum = u32::from_be_bytes(data[0..4].try_into().unwrap());
+    if entnum != ENTERPRISE_NUMBER {
+        return Err(invalid("Nonrecognized vendor code"));
+    }
+    Ok(&data[4..])
+}
+
+/// Strips the RFC 3925 enterprise number and data-len byte. Some servers
+/// get data-len wrong, so a mismatch is only logged.
+fn dhcp4_vendor_data(data: &[u8]) -> std::io::Result<&[u8]> {
+    let data = check_enterprise(data)?;
+    if data.is_empty() {
+        return Err(invalid("Truncated vendor option"));
+    }
+    let len = data[0] as usize;
+    if len + 1 != data.len() {
+        log::warn!("Invalid NTT option length");
+    }
+    Ok(&data[1..(len + 1).min(data.len())])
+}
+
+fn encode_domain(domain: &str) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    for label in domain.trim_end_matches('.').split('.') {
+        if label.len() > 63 {
+            return Err(invalid("Domain label too long"));
+        }
+        data.push(label.len() as u8);
+        data.extend_from_slice(label.as_bytes());
+    }
+    data.push(0);
+    Ok(data)
+}
+
+fn decode_domain(data: &[u8]) -> String {
+    let mut i = 0usize;
+    let mut labels = Vec::new();
+    while i < data.len() {
+        let label_len = data[i] as usize;
+        if label_len == 0 {
+            break;
+        }
+        let start_i = i + 1;
+        let end_i = start_i + label_len;
+        if end_i > data.len() {
+            break;
+        }
+        labels.push(String::from_utf8_lossy(&data[start_i..end_i]).into_owned());
+        i = end_i;
+    }
+    labels.join(".")
+}
+
+impl NttVendorInfo {
+    fn suboptions(&self) -> std::io::Result<Vec<(u16, Vec<u8>)>> {
+        let mut subopts = Vec::new();
+        if let Some(mac) = self.mac_addr {
+            subopts.push((SUBOPT_MAC_ADDR, mac.to_vec()));
+        }
+        if let Some(main) = &self.main_number {
+            subopts.push((SUBOPT_MAIN_NUMBER, main.as_bytes().to_vec()));
+        }
+        for add in &self.add_numbers {
+            subopts.push((SUBOPT_ADD_NUMBER, add.as_bytes().to_vec()));
+        }
+        if let Some(domain) = &self.sip_domain {
+            subopts.push((SUBOPT_SIP_DOMAIN, encode_domain(domain)?));
+        }
+        Ok(subopts)
+    }
+
+    fn add_suboption(&mut self, code: u16, data: &[u8]) -> std::io::Result<()> {
+        match code {
+            SUBOPT_MAC_ADDR => {
+                let mac: [u8; 6] = data.try_into().map_err(|_| invalid("Invalid NTT MAC address suboption"))?;
+                self.mac_addr = Some(mac);
+            },
+            SUBOPT_MAIN_NUMBER => {
+                log::debug!("Main number data: {:?}", data);
+                let main = String::from_utf8(data.to_vec()).unwrap_or_default();
+                if !main.is_empty() {
+                    self.main_number = Some(main);
+                }
+            },
+            SUBOPT_ADD_NUMBER => {
+                log::debug!("Additional number data: {:?}", data);
+                let add = String::from_utf8(data.to_vec()).unwrap_or_default();
+                if !add.is_empty() {
+                    self.add_numbers.push(add);
+                }
+            },
+            SUBOPT_SIP_DOMAIN => {
+                self.sip_domain = Some(decode_domain(data));
+            },
+            _ => {
+                log::debug!("Unknown NTT suboption: {}", code);
+            },
+        }
+        Ok(())
+    }
+
+    /// Fails if the server echoed a MAC address other than ours.
+    pub fn verify_mac(&self, local_if_mac: [u8; 6]) -> std::io::Result<()> {
+        match self.mac_addr {
+            Some(mac) if mac != local_if_mac => Err(invalid("NTT vendor option MAC address mismatch")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Encodes the payload of DHCPv4 option 125; fails if a suboption or
+    /// the whole does not fit its one-byte length.
+    pub fn encode_dhcp4(&self) -> std::io::Result<Vec<u8>> {
+        let mut subopt_data = Vec::new();
+        for (code, data) in self.suboptions()? {
+            let len = u8::try_from(data.len()).map_err(|_| invalid("NTT suboption too long"))?;
+            subopt_data.push(code as u8);
+            subopt_data.push(len);
+            subopt_data.extend_from_slice(&data);
+        }
+        let len = u8::try_from(subopt_data.len()).map_err(|_| invalid("NTT vendor option too long"))?;
+        let mut data = ENTERPRISE_NUMBER.to_be_bytes().to_vec();
+        data.push(len);
+        data.extend_from_slice(&subopt_data);
+        Ok(data)
+    }
+
+    /// Builds the info from `(code, data)` suboptions, e.g. those of one
+    /// enterprise in an option carrying several.
+    pub fn from_suboptions<'a, I>(subopts: I) -> std::io::Result<Self>
+    where
+        I: IntoIterator<Item = (u16, &'a [u8])>,
+    {
+        let mut info = Self::default();
+        for (code, data) in subopts {
+            info.add_suboption(code, data)?;
+        }
+        Ok(info)
+    }
+
+    /// Decodes the payload of DHCPv4 option 125, which may carry other
+    /// enterprises besides ours. A data-len running past the end of the
+    /// option is cut short, as some servers get it wrong.
+    pub fn decode_dhcp4(data: &[u8]) -> std::io::Result<Self> {
+        let mut pos = 0usize;
+        while pos + 5 <= data.len() {
+            let enterprise = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap());
+            let len = data[pos + 4] as usize;
+            let end = (pos + 5 + len).min(data.len());
+            if enterprise != ENTERPRISE_NUMBER {
+                pos = end;
+                continue;
+            }
+            if end - pos - 5 != len {
+                log::warn!("Invalid NTT option length");
+            }
+            let data = &data[pos + 5..end];
+            let mut info = Self::default();
+            let mut pos = 0usize;
+            while pos < data.len() {
+                if pos + 2 > data.len() {
+                    return Err(invalid("Truncated NTT suboption"));
+                }
+                let code = data[pos] as u16;
+                let end = pos + 2 + data[pos + 1] as usize;
+                if end > data.len() {
+                    return Err(invalid("Truncated NTT suboption"));
+                }
+                info.add_suboption(code, &data[pos + 2..end])?;
+                pos = end;
+            }
+            return Ok(info);
+        }
+        Err(invalid("Nonrecognized vendor code"))
+    }
+
+    /// Encodes the payload of DHCPv6 option 17.
+    pub fn encode_dhcp6(&self) -> std::io::Result<Vec<u8>> {
+        let mut data = ENTERPRISE_NUMBER.to_be_bytes().to_vec();
+        for (code, subopt) in self.suboptions()? {
+            let len = u16::try_from(subopt.len()).map_err(|_| invalid("NTT suboption too long"))?;
+            data.extend_from_slice(&code.to_be_bytes());
+            data.extend_from_slice(&len.to_be_bytes());
+            data.extend_from_slice(&subopt);
+        }
+        Ok(data)
+    }
+
+    /// Decodes the payload of DHCPv6 option 17.
+    pub fn decode_dhcp6(data: &[u8]) -> std::io::Result<Self> {
+        let data = check_enterprise(data)?;
+        let mut info = Self::default();
+        let mut pos = 0usize;
+        while pos < data.len() {
+            if pos + 4 > data.len() {
+                return Err(invalid("Truncated NTT suboption"));
+            }
+            let code = u16::from_be_bytes([data[pos], data[pos + 1]]);
+            let end = pos + 4 + u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+            if end > data.len() {
+                return Err(invalid("Truncated NTT suboption"));
+            }
+            info.add_suboption(code, &data[pos + 4..end])?;
+            pos = end;
+        }
+        Ok(info)
+    }
+}
+
+/// Encodes the payload of DHCPv4 option 124, identifying us by MAC address.
+pub fn encode_dhcp4_vendor_class(local_if_mac: [u8; 6]) -> Vec<u8> {
+    let mut data = ENTERPRISE_NUMBER.to_be_bytes().to_vec();
+    data.push(1 + local_if_mac.len() as u8);
+    data.push(local_if_mac.len() as u8);
+    data.extend_from_slice(&local_if_mac);
+    data
+}
+
+/// Decodes the payload of DHCPv4 option 124.
+pub fn decode_dhcp4_vendor_class(data: &[u8]) -> std::io::Result<[u8; 6]> {
+    let data = dhcp4_vendor_data(data)?;
+    if data.first() != Some(&6) {
+        return Err(invalid("Invalid NTT vendor class"));
+    }
+    data[1..].try_into().map_err(|_| invalid("Invalid NTT vendor class"))
+}
+
+/// Encodes the payload of DHCPv6 option 16, identifying us by MAC address.
+pub fn encode_dhcp6_vendor_class(local_if_mac: [u8; 6]) -> Vec<u8> {
+    let mut data = ENTERPRISE_NUMBER.to_be_bytes().to_vec();
+    data.extend_from_slice(&(local_if_mac.len() as u16).to_be_bytes());
+    data.extend_from_slice(&local_if_mac);
+    data
+}
+
+/// Decodes the payload of DHCPv6 option 16.
+pub fn decode_dhcp6_vendor_class(data: &[u8]) -> std::io::Result<[u8; 6]> {
+    let data = check_enterprise(data)?;
+    if data.len() != 8 || data[0..2] != [0, 6] {
+        return Err(invalid("Invalid NTT vendor class"));
+    }
+    Ok(data[2..].try_into().unwrap())
+}
@@ -0,0 +1,244 @@
+
+//! Raw option access and user-registered option decoders.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// An option as it appeared on the wire, without code and length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawOption {
+    pub code: u16,
+    pub data: Vec<u8>,
+}
+
+/// Identifies an option, or a suboption inside a vendor-specific option
+/// (DHCPv4 option 125, DHCPv6 option 17).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OptionKey {
+    Option(u16),
+    Vendor { enterprise: u32, code: u16 },
+}
+
+trait OptionValue: Any + Debug + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Any + Debug + Send + Sync> OptionValue for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The value returned by a registered decoder.
+#[derive(Debug, Clone)]
+pub struct DecodedOption(Arc<dyn OptionValue>);
+
+impl DecodedOption {
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        // deref first: the Arc itself also implements OptionValue
+        (*self.0).as_any().downcast_ref()
+    }
+}
+
+type DecoderFn = Arc<dyn Fn(&[u8]) -> std::io::Result<DecodedOption> + Send + Sync>;
+
+/// Decoders for options the crate does not interpret itself.
+#[derive(Clone, Default)]
+pub struct OptionDecoders {
+    decoders: HashMap<OptionKey, DecoderFn>,
+}
+
+impl Debug for OptionDecoders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.decoders.keys()).finish()
+    }
+}
+
+impl OptionDecoders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a decoder for `key`, replacing any earlier one.
+    pub fn register<T, F>(&mut self, key: OptionKey, decoder: F)
+    where
+        T: Any + Debug + Send + Sync,
+        F: Fn(&[u8]) -> std::io::Result<T> + Send + Sync + 'static,
+    {
+        let decoder: DecoderFn = Arc::new(move |data| Ok(DecodedOption(Arc::new(decoder(data)?))));
+        self.decoders.insert(key, decoder);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decoders.is_empty()
+    }
+
+    fn decode_into(&self, key: OptionKey, data: &[u8], out: &mut CustomOptions) {
+        let Some(decoder) = self.decoders.get(&key) else {
+            return;
+        };
+        match decoder(data) {
+            Ok(value) => {
+                out.0.entry(key).or_default().push(value);
+            },
+            Err(e) => {
+                log::warn!("Custom decoder for {:?} failed: {}", key, e);
+            },
+        }
+    }
+
+    /// Runs the decoders over the options of a DHCPv4 message, including
+    /// the suboptions of option 125.
+    pub fn decode_dhcp4(&self, raw_options: &[RawOption]) -> CustomOptions {
+        let mut out = CustomOptions::default();
+        if self.is_empty() {
+            return out;
+        }
+        for opt in raw_options {
+            self.decode_into(OptionKey::Option(opt.code), &opt.data, &mut out);
+            if opt.code == 125 {
+                for (enterprise, code, data) in dhcp4_vendor_suboptions(&opt.data) {
+                    self.decode_into(OptionKey::Vendor { enterprise, code }, data, &mut out);
+                }
+            }
+        }
+        out
+    }
+
+    /// Runs the decoders over DHCPv6 options, including the suboptions of
+    /// option 17.
+    pub fn decode_dhcp6(&self, raw_options: &[RawOption]) -> CustomOptions {
+        let mut out = CustomOptions::default();
+        if self.is_empty() {
+            return out;
+        }
+        for opt in raw_options {
+            self.decode_into(OptionKey::Option(opt.code), &opt.data, &mut out);
+            if opt.code == 17 {
+                for (enterprise, code, data) in dhcp6_vendor_suboptions(&opt.data) {
+                    self.decode_into(OptionKey::Vendor { enterprise, code }, data, &mut out);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Values produced by registered decoders, keyed by option.
+#[derive(Debug, Clone, Default)]
+pub struct CustomOptions(HashMap<OptionKey, Vec<DecodedOption>>);
+
+impl CustomOptions {
+    /// Returns the first value decoded for `key`, if it has type `T`.
+    pub fn get<T: Any>(&self, key: OptionKey) -> Option<&T> {
+        self.0.get(&key)?.first()?.downcast_ref()
+    }
+
+    /// Returns every value decoded for `key`, for options that may repeat.
+    pub fn get_all(&self, key: OptionKey) -> &[DecodedOption] {
+        self.0.get(&key).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Splits the options field of a BOOTP packet, concatenating options that
+/// were split across several instances (RFC 3396).
+pub fn parse_dhcp4_options(packet: &[u8]) -> Vec<RawOption> {
+    let mut options: Vec<RawOption> = Vec::new();
+    // fixed header (236 Bytes) and magic cookie
+    let mut pos = 240usize;
+    while pos < packet.len() {
+        let code = packet[pos];
+        match code {
+            0 => {
+                pos += 1;
+                continue;
+            },
+            255 => break,
+            _ => {},
+        }
+        if pos + 2 > packet.len() {
+            break;
+        }
+        let end = pos + 2 + packet[pos + 1] as usize;
+        if end > packet.len() {
+            break;
+        }
+        let data = &packet[pos + 2..end];
+        match options.iter_mut().find(|o| o.code == code as u16) {
+            Some(opt) => opt.data.extend_from_slice(data),
+            None => options.push(RawOption {
+                code: code as u16,
+                data: data.to_vec(),
+            }),
+        }
+        pos = end;
+    }
+    options
+}
+
+/// Splits DHCPv6 options, e.g. the body of a message after its 4-Byte header.
+pub fn parse_dhcp6_options(data: &[u8]) -> Vec<RawOption> {
+    let mut options = Vec::new();
+    let mut pos = 0usize;
+    while pos + 4 <= data.len() {
+        let code = u16::from_be_bytes([data[pos], data[pos + 1]]);
+        let end = pos + 4 + u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        if end > data.len() {
+            break;
+        }
+        options.push(RawOption {
+            code,
+            data: data[pos + 4..end].to_vec(),
+        });
+        pos = end;
+    }
+    options
+}
+
+/// Splits an RFC 3925 V-I Vendor-Specific Information option, which may
+/// carry several enterprises. A data-len running past the end of the
+/// option is cut short, as some servers get it wrong.
+pub(crate) fn dhcp4_vendor_suboptions(data: &[u8]) -> Vec<(u32, u16, &[u8])> {
+    let mut subopts = Vec::new();
+    let mut pos = 0usize;
+    while pos + 5 <= data.len() {
+        let enterprise = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap());
+        let end = (pos + 5 + data[pos + 4] as usize).min(data.len());
+        let mut sub = pos + 5;
+        while sub + 2 <= end {
+            let sub_end = sub + 2 + data[sub + 1] as usize;
+            if sub_end > end {
+                break;
+            }
+            subopts.push((enterprise, data[sub] as u16, &data[sub + 2..sub_end]));
+            sub = sub_end;
+        }
+        pos = end;
+    }
+    subopts
+}
+
+fn dhcp6_vendor_suboptions(data: &[u8]) -> Vec<(u32, u16, &[u8])> {
+    let mut subopts = Vec::new();
+    if data.len() < 4 {
+        return subopts;
+    }
+    let enterprise = u32::from_be_bytes(data[0..4].try_into().unwrap());
+    let mut pos = 4usize;
+    while pos + 4 <= data.len() {
+        let code = u16::from_be_bytes([data[pos], data[pos + 1]]);
+        let end = pos + 4 + u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        if end > data.len() {
+            break;
+        }
+        subopts.push((enterprise, code, &data[pos + 4..end]));
+        pos = end;
+    }
+    subopts
+}
@@ -0,0 +1,58 @@
+
+//! Small helpers for the key=value files the crate persists state in.
+
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+/// The error for malformed input, from the wire or from a file.
+pub(crate) fn invalid(msg: &'static str) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Replaces `path` with `data` so that readers see either the old or the
+/// new contents, never a partial write. The file is readable by its owner
+/// only, as leases may hold authentication keys.
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut tmp_name = path.file_name()
+        .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "Invalid file path"))?
+        .to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    {
+        let mut file = OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+    }
+    std::fs::rename(&tmp_path, path)?;
+    if let Some(dir) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+pub(crate) fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn from_hex(s: &str) -> std::io::Result<Vec<u8>> {
+    let invalid = || std::io::Error::new(ErrorKind::InvalidData, "Invalid hex string");
+    let s = s.trim();
+    if !s.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    (0..s.len()).step_by(2)
+        .map(|i| s.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()).ok_or_else(invalid))
+        .collect()
+}
+
+/// Parses `key=value` lines, skipping blanks and `#` comments.
+pub(crate) fn parse_kv(contents: &str) -> Vec<(&str, &str)> {
+    contents.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|l| l.split_once('='))
+        .map(|(k, v)| (k.trim(), v.trim()))
+        .collect()
+}
@@ -0,0 +1,208 @@
+
+//! Carves a delegated prefix into prefixes for LAN interfaces.
+//!
+//! Each LAN keeps its subnet ID, i.e. its offset within the delegation,
+//! across renumbering, so that only the delegated part of its prefix
+//! changes (RFC 7084 section 4.3).
+
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::net::Ipv6Addr;
+use std::path::Path;
+
+use ipnet::Ipv6Net;
+
+use crate::ipv6::PdPrefix;
+use crate::persist::{parse_kv, write_atomic};
+
+/// A LAN interface wanting a prefix of the delegation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanRequest {
+    pub name: String,
+    /// At most 64, so that SLAAC works.
+    pub prefix_len: u8,
+    /// Preferred subnet ID, counted in prefixes of `prefix_len`.
+    pub hint: Option<u64>,
+}
+
+impl LanRequest {
+    pub fn new(name: &str, prefix_len: u8) -> Self {
+        Self {
+            name: name.to_string(),
+            prefix_len,
+            hint: None,
+        }
+    }
+
+    pub fn with_hint(mut self, subnet_id: u64) -> Self {
+        self.hint = Some(subnet_id);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanAssignment {
+    pub name: String,
+    pub subnet_id: u64,
+    pub prefix: Ipv6Net,
+}
+
+/// Assignments for one delegation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixPlan {
+    pub delegated: Ipv6Net,
+    pub excluded: Option<Ipv6Net>,
+    pub assignments: Vec<LanAssignment>,
+    /// LANs that did not fit.
+    pub unassigned: Vec<String>,
+}
+
+impl PrefixPlan {
+    pub fn get(&self, name: &str) -> Option<&LanAssignment> {
+        self.assignments.iter().find(|a| a.name == name)
+    }
+}
+
+/// Plans LAN prefixes, remembering the subnet ID given to each LAN.
+#[derive(Debug, Clone)]
+pub struct PrefixPlanner {
+    lans: Vec<LanRequest>,
+    /// Subnet ID and prefix length last assigned, by LAN name.
+    subnet_ids: BTreeMap<String, (u8, u64)>,
+    last: Option<PrefixPlan>,
+}
+
+fn subnet(delegated: Ipv6Net, prefix_len: u8, subnet_id: u64) -> Option<Ipv6Net> {
+    let bits = prefix_len.checked_sub(delegated.prefix_len())?;
+    if prefix_len > 64 || (bits < 64 && subnet_id >> bits != 0) {
+        return None;
+    }
+    if bits == 0 {
+        return Some(delegated);
+    }
+    let addr = u128::from(delegated.network()) | ((subnet_id as u128) << (128 - prefix_len as u32));
+    Ipv6Net::new(Ipv6Addr::from(addr), prefix_len).ok()
+}
+
+fn overlaps(a: &Ipv6Net, b: &Ipv6Net) -> bool {
+    a.contains(b) || b.contains(a)
+}
+
+/// The lowest subnet ID whose prefix overlaps nothing taken, jumping
+/// past each taken prefix rather than trying every ID in between.
+fn lowest_free(delegated: Ipv6Net, prefix_len: u8, taken: &[Ipv6Net]) -> Option<(u64, Ipv6Net)> {
+    let bits = prefix_len.checked_sub(delegated.prefix_len()).filter(|_| prefix_len <= 64)?;
+    let count = 1u128 << bits;
+    let shift = 128 - prefix_len as u32;
+    let base = u128::from(delegated.network());
+    let mut id = 0u128;
+    while id < count {
+        let net = subnet(delegated, prefix_len, id as u64)?;
+        let Some(t) = taken.iter().find(|t| overlaps(t, &net)) else {
+            return Some((id as u64, net));
+        };
+        let end = u128::from(t.broadcast()).max(u128::from(net.broadcast()));
+        id = ((end - base) >> shift) + 1;
+    }
+    None
+}
+
+impl PrefixPlanner {
+    pub fn new(lans: Vec<LanRequest>) -> Self {
+        Self {
+            lans,
+            subnet_ids: BTreeMap::new(),
+            last: None,
+        }
+    }
+
+    pub fn lans(&self) -> &[LanRequest] {
+        &self.lans
+    }
+
+    /// Assigns prefixes within `pd`. LANs with a hint are placed first,
+    /// then those remembered from earlier plans, then the rest from the
+    /// largest prefix down, each at the lowest free subnet ID.
+    pub fn plan(&mut self, pd: &PdPrefix) -> PrefixPlan {
+        let delegated = pd.net();
+        let mut taken: Vec<Ipv6Net> = pd.excluded.into_iter().collect();
+        let mut assignments = Vec::new();
+        let mut unassigned = Vec::new();
+
+        let mut order: Vec<&LanRequest> = self.lans.iter().collect();
+        order.sort_by_key(|lan| {
+            let remembered = self.subnet_ids.get(&lan.name).is_some_and(|(len, _)| *len == lan.prefix_len);
+            (lan.hint.is_none(), !remembered, lan.prefix_len)
+        });
+
+        for lan in order {
+            let remembered = self.subnet_ids.get(&lan.name)
+                .filter(|(len, _)| *len == lan.prefix_len)
+                .map(|(_, id)| *id);
+            let free = |id: u64| subnet(delegated, lan.prefix_len, id).filter(|net| !taken.iter().any(|t| overlaps(t, net)));
+            let preferred = lan.hint.into_iter().chain(remembered)
+                .find_map(|id| free(id).map(|net| (id, net)));
+            let found = preferred.or_else(|| lowest_free(delegated, lan.prefix_len, &taken));
+            match found {
+                Some((subnet_id, prefix)) => {
+                    taken.push(prefix);
+                    self.subnet_ids.insert(lan.name.clone(), (lan.prefix_len, subnet_id));
+                    assignments.push(LanAssignment { name: lan.name.clone(), subnet_id, prefix });
+                },
+                None => {
+                    log::warn!("No /{} left in {} for {}", lan.prefix_len, delegated, lan.name);
+                    unassigned.push(lan.name.clone());
+                },
+            }
+        }
+
+        // report in the order the LANs were given
+        assignments.sort_by_key(|a| self.lans.iter().position(|lan| lan.name == a.name));
+        let plan = PrefixPlan { delegated, excluded: pd.excluded, assignments, unassigned };
+        self.last = Some(plan.clone());
+        plan
+    }
+
+    /// Plans again if the delegation differs from the last one planned
+    /// for; returns the new plan, or `None` if nothing changed.
+    pub fn update(&mut self, pd: &PdPrefix) -> Option<PrefixPlan> {
+        if self.last.as_ref().is_some_and(|last| last.delegated == pd.net() && last.excluded == pd.excluded) {
+            return None;
+        }
+        Some(self.plan(pd))
+    }
+
+    /// Remembered subnet IDs as `name=len/id` lines, the ID in hex.
+    pub fn encode(&self) -> String {
+        let mut s = String::new();
+        for (name, (len, id)) in &self.subnet_ids {
+            s.push_str(&format!("{}={}/{:x}\n", name, len, id));
+        }
+        s
+    }
+
+    pub fn decode(&mut self, s: &str) -> std::io::Result<()> {
+        let invalid = || std::io::Error::new(ErrorKind::InvalidData, "Invalid prefix plan");
+        for (name, value) in parse_kv(s) {
+            let (len, id) = value.split_once('/').ok_or_else(invalid)?;
+            let len = len.parse().map_err(|_| invalid())?;
+            let id = u64::from_str_radix(id, 16).map_err(|_| invalid())?;
+            self.subnet_ids.insert(name.to_string(), (len, id));
+        }
+        Ok(())
+    }
+
+    /// Loads subnet IDs stored by [`Self::store`]; a missing file is not
+    /// an error.
+    pub fn load(&mut self, path: &Path) -> std::io::Result<()> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => self.decode(&contents),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn store(&self, path: &Path) -> std::io::Result<()> {
+        write_atomic(path, self.encode().as_bytes())
+    }
+}
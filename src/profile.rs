@@ -0,0 +1,142 @@
+
+//! ISP provisioning profiles.
+//!
+//! A profile decides which vendor-specific options, request lists and
+//! identifiers the clients send, and which vendor options they decode.
+//! Implement [`ProvisioningProfile`] for ISPs not covered here.
+
+use std::fmt::Debug;
+
+use dhcproto::{v4, v6};
+
+use crate::identity::{Duid, HW_TYPE_ETHERNET};
+use crate::ntt;
+
+pub trait ProvisioningProfile: Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Parameter Request List for DHCPDISCOVER.
+    fn dhcp4_discover_params(&self) -> Vec<v4::OptionCode> {
+        vec![
+            v4::OptionCode::SubnetMask,
+            v4::OptionCode::Router,
+        ]
+    }
+
+    /// Parameter Request List for DHCPREQUEST.
+    fn dhcp4_request_params(&self) -> Vec<v4::OptionCode> {
+        vec![
+            v4::OptionCode::SubnetMask,
+            v4::OptionCode::Router,
+            v4::OptionCode::ClasslessStaticRoute,
+            v4::OptionCode::StaticRoutingTable,
+            v4::OptionCode::Unknown(249),
+        ]
+    }
+
+    /// Client identifier (option 61) contents; the hardware type and
+    /// MAC address by default (RFC 2132 section 9.14).
+    fn dhcp4_client_id(&self, local_if_mac: [u8; 6]) -> Vec<u8> {
+        let mut id = vec![HW_TYPE_ETHERNET as u8];
+        id.extend_from_slice(&local_if_mac);
+        id
+    }
+
+    /// Vendor options added to a DHCPv4 message of the given type.
+    fn dhcp4_vendor_options(&self, _msg_type: v4::MessageType, _local_if_mac: [u8; 6]) -> Vec<v4::DhcpOption> {
+        Vec::new()
+    }
+
+    /// DUID sent as the DHCPv6 Client Identifier; DUID-LL by default.
+    fn dhcp6_duid(&self, local_if_mac: [u8; 6]) -> Vec<u8> {
+        Duid::ll_from_mac(local_if_mac).to_bytes()
+    }
+
+    /// Option Request Option for DHCPv6.
+    fn dhcp6_oro(&self) -> Vec<v6::OptionCode> {
+        vec![
+            v6::OptionCode::IAPD,
+            v6::OptionCode::DomainNameServers,
+            v6::OptionCode::DomainSearchList,
+        ]
+    }
+
+    /// Vendor options added to a DHCPv6 message of the given type.
+    fn dhcp6_vendor_options(&self, _msg_type: v6::MessageType, _local_if_mac: [u8; 6]) -> Vec<v6::DhcpOption> {
+        Vec::new()
+    }
+
+    /// Whether vendor options for `enterprise` should be decoded into the response.
+    fn decodes_vendor(&self, _enterprise: u32) -> bool {
+        false
+    }
+}
+
+/// Plain RFC 2131 / RFC 8415 client without vendor options.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GenericProfile;
+
+impl ProvisioningProfile for GenericProfile {
+    fn name(&self) -> &str {
+        "generic"
+    }
+}
+
+/// NTT NGN (FLET'S Hikari) provisioning, including the enterprise 210
+/// vendor options carrying our MAC address and the SIP parameters.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NttNgnProfile;
+
+impl ProvisioningProfile for NttNgnProfile {
+    fn name(&self) -> &str {
+        "ntt-ngn"
+    }
+
+    /// The bare MAC address, without a hardware type.
+    fn dhcp4_client_id(&self, local_if_mac: [u8; 6]) -> Vec<u8> {
+        local_if_mac.to_vec()
+    }
+
+    fn dhcp4_request_params(&self) -> Vec<v4::OptionCode> {
+        vec![
+            v4::OptionCode::SubnetMask,
+            v4::OptionCode::Router,
+            v4::OptionCode::Unknown(120),
+            v4::OptionCode::ClasslessStaticRoute,
+            v4::OptionCode::StaticRoutingTable,
+            v4::OptionCode::Unknown(249),
+            v4::OptionCode::Unknown(125),
+        ]
+    }
+
+    fn dhcp4_vendor_options(&self, msg_type: v4::MessageType, local_if_mac: [u8; 6]) -> Vec<v4::DhcpOption> {
+        if msg_type != v4::MessageType::Request {
+            return Vec::new();
+        }
+        let data = ntt::encode_dhcp4_vendor_class(local_if_mac);
+        vec![v4::DhcpOption::Unknown(v4::UnknownOption::new(v4::OptionCode::Unknown(124), data))]
+    }
+
+    fn dhcp6_oro(&self) -> Vec<v6::OptionCode> {
+        vec![
+            v6::OptionCode::IAPD,
+            v6::OptionCode::SipServerA,
+            v6::OptionCode::SntpServers,
+            v6::OptionCode::DomainNameServers,
+            v6::OptionCode::DomainSearchList,
+        ]
+    }
+
+    fn dhcp6_vendor_options(&self, msg_type: v6::MessageType, local_if_mac: [u8; 6]) -> Vec<v6::DhcpOption> {
+        if msg_type != v6::MessageType::Request {
+            return Vec::new();
+        }
+        // dhcproto miscomputes the VendorClass length, so encode it by hand.
+        let data = ntt::encode_dhcp6_vendor_class(local_if_mac);
+        vec![v6::DhcpOption::Unknown(v6::UnknownOption::new(v6::OptionCode::VendorClass, data))]
+    }
+
+    fn decodes_vendor(&self, enterprise: u32) -> bool {
+        enterprise == ntt::ENTERPRISE_NUMBER
+    }
+}
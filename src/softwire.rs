@@ -0,0 +1,187 @@
+
+//! Softwire options for IPv4 over IPv6: the DS-Lite AFTR-Name (RFC 6334)
+//! and the MAP-E, MAP-T and lw4o6 containers of RFC 7598.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use ipnet::{Ipv4Net, Ipv6Net};
+
+use crate::persist::invalid;
+
+pub const OPTION_AFTR_NAME: u16 = 64;
+pub const OPTION_S46_RULE: u16 = 89;
+pub const OPTION_S46_BR: u16 = 90;
+pub const OPTION_S46_DMR: u16 = 91;
+pub const OPTION_S46_V4V6BIND: u16 = 92;
+pub const OPTION_S46_PORTPARAMS: u16 = 93;
+pub const OPTION_S46_CONT_MAPE: u16 = 94;
+pub const OPTION_S46_CONT_MAPT: u16 = 95;
+pub const OPTION_S46_CONT_LW: u16 = 96;
+
+/// Port set of a shared IPv4 address (OPTION_S46_PORTPARAMS).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct S46PortParams {
+    /// Number of high-order bits excluding the well-known ports, `a` in RFC 7597.
+    pub offset: u8,
+    pub psid_len: u8,
+    /// The PSID value, right-aligned.
+    pub psid: u16,
+}
+
+/// A MAP rule (OPTION_S46_RULE).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S46Rule {
+    /// Whether this is also a Forwarding Mapping Rule.
+    pub fmr: bool,
+    pub ea_len: u8,
+    pub ipv4_prefix: Ipv4Net,
+    pub ipv6_prefix: Ipv6Net,
+    pub port_params: Option<S46PortParams>,
+}
+
+/// An lw4o6 binding (OPTION_S46_V4V6BIND).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S46V4v6Bind {
+    pub ipv4_addr: Ipv4Addr,
+    pub bind_prefix: Ipv6Net,
+    pub port_params: Option<S46PortParams>,
+}
+
+/// Contents of a MAP-E, MAP-T or lw4o6 container.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct S46Container {
+    pub rules: Vec<S46Rule>,
+    /// Border relay addresses (MAP-E and lw4o6).
+    pub br_addrs: Vec<Ipv6Addr>,
+    /// Default Mapping Rule prefix (MAP-T).
+    pub dmr: Option<Ipv6Net>,
+    /// Binding (lw4o6).
+    pub v4v6bind: Option<S46V4v6Bind>,
+}
+
+/// Splits DHCPv6-style (16-bit code and length) options.
+fn suboptions(mut data: &[u8]) -> std::io::Result<Vec<(u16, &[u8])>> {
+    let mut opts = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 4 {
+            return Err(invalid("Truncated softwire option"));
+        }
+        let code = u16::from_be_bytes([data[0], data[1]]);
+        let len = u16::from_be_bytes([data[2], data[3]]) as usize;
+        let end = 4 + len;
+        if end > data.len() {
+            return Err(invalid("Truncated softwire option"));
+        }
+        opts.push((code, &data[4..end]));
+        data = &data[end..];
+    }
+    Ok(opts)
+}
+
+/// Reads a prefix length followed by the shortest run of bytes holding it.
+fn decode_prefix6(data: &[u8]) -> std::io::Result<(Ipv6Net, &[u8])> {
+    let len = *data.first().ok_or_else(|| invalid("Truncated IPv6 prefix"))?;
+    if len > 128 {
+        return Err(invalid("Invalid IPv6 prefix length"));
+    }
+    let n = (len as usize).div_ceil(8);
+    if data.len() < 1 + n {
+        return Err(invalid("Truncated IPv6 prefix"));
+    }
+    let mut addr = [0u8; 16];
+    addr[..n].copy_from_slice(&data[1..1 + n]);
+    let net = Ipv6Net::new(addr.into(), len).unwrap().trunc();
+    Ok((net, &data[1 + n..]))
+}
+
+fn decode_port_params(opts: &[(u16, &[u8])]) -> std::io::Result<Option<S46PortParams>> {
+    let Some((_, data)) = opts.iter().find(|(code, _)| *code == OPTION_S46_PORTPARAMS) else {
+        return Ok(None);
+    };
+    if data.len() != 4 {
+        return Err(invalid("Invalid S46 port parameters"));
+    }
+    let (offset, psid_len) = (data[0], data[1]);
+    if offset > 15 || psid_len > 16 || offset + psid_len > 16 {
+        return Err(invalid("Invalid S46 port parameters"));
+    }
+    let raw = u16::from_be_bytes([data[2], data[3]]);
+    let psid = if psid_len == 0 { 0 } else { raw >> (16 - psid_len) };
+    Ok(Some(S46PortParams { offset, psid_len, psid }))
+}
+
+impl S46Rule {
+    pub fn decode(data: &[u8]) -> std::io::Result<Self> {
+        if data.len() < 7 {
+            return Err(invalid("Truncated S46 rule"));
+        }
+        let fmr = data[0] & 0x01 != 0;
+        let ea_len = data[1];
+        let prefix4_len = data[2];
+        let ipv4_prefix = Ipv4Net::new(Ipv4Addr::new(data[3], data[4], data[5], data[6]), prefix4_len)
+            .map_err(|_| invalid("Invalid IPv4 prefix length"))?
+            .trunc();
+        let (ipv6_prefix, rest) = decode_prefix6(&data[7..])?;
+        if ea_len > 48 {
+            return Err(invalid("Invalid EA-bits length"));
+        }
+        let port_params = decode_port_params(&suboptions(rest)?)?;
+        Ok(Self { fmr, ea_len, ipv4_prefix, ipv6_prefix, port_params })
+    }
+}
+
+impl S46V4v6Bind {
+    pub fn decode(data: &[u8]) -> std::io::Result<Self> {
+        if data.len() < 5 {
+            return Err(invalid("Truncated S46 binding"));
+        }
+        let ipv4_addr = Ipv4Addr::new(data[0], data[1], data[2], data[3]);
+        let (bind_prefix, rest) = decode_prefix6(&data[4..])?;
+        let port_params = decode_port_params(&suboptions(rest)?)?;
+        Ok(Self { ipv4_addr, bind_prefix, port_params })
+    }
+}
+
+impl S46Container {
+    /// Decodes the payload of option 94, 95 or 96. Unknown sub-options are
+    /// skipped.
+    pub fn decode(data: &[u8]) -> std::io::Result<Self> {
+        let mut container = Self::default();
+        for (code, data) in suboptions(data)? {
+            match code {
+                OPTION_S46_RULE => container.rules.push(S46Rule::decode(data)?),
+                OPTION_S46_BR => {
+                    let addr: [u8; 16] = data.try_into().map_err(|_| invalid("Invalid S46 BR option"))?;
+                    container.br_addrs.push(addr.into());
+                },
+                OPTION_S46_DMR => container.dmr = Some(decode_prefix6(data)?.0),
+                OPTION_S46_V4V6BIND => container.v4v6bind = Some(S46V4v6Bind::decode(data)?),
+                _ => {},
+            }
+        }
+        Ok(container)
+    }
+}
+
+/// Decodes the AFTR-Name FQDN, in DNS wire format, without the trailing dot.
+pub fn decode_aftr_name(data: &[u8]) -> std::io::Result<String> {
+    let mut labels = Vec::new();
+    let mut rest = data;
+    loop {
+        let (&len, tail) = rest.split_first().ok_or_else(|| invalid("Truncated AFTR-Name"))?;
+        if len == 0 {
+            break;
+        }
+        let len = len as usize;
+        if len > 63 || tail.len() < len {
+            return Err(invalid("Invalid AFTR-Name label"));
+        }
+        let label = std::str::from_utf8(&tail[..len]).map_err(|_| invalid("Invalid AFTR-Name label"))?;
+        labels.push(label.to_ascii_lowercase());
+        rest = &tail[len..];
+    }
+    if labels.is_empty() {
+        return Err(invalid("Empty AFTR-Name"));
+    }
+    Ok(labels.join("."))
+}
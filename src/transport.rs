@@ -0,0 +1,51 @@
+//! How the clients send and receive their packets.
+
+use std::fmt::Debug;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use crate::clock::Clock;
+
+/// A datagram socket as the clients use it, so that tests can put a fake
+/// server in its place.
+pub trait Transport: Debug + Send + Sync {
+    fn send_to(&self, packet: &[u8], dest: SocketAddr) -> std::io::Result<()>;
+
+    /// Receives one packet, waiting at most `timeout` as measured by
+    /// `clock`. Fails with `WouldBlock` or `TimedOut` if none arrived.
+    fn recv(&self, clock: &dyn Clock, timeout: Duration) -> std::io::Result<Vec<u8>>;
+}
+
+/// A bound UDP socket. Waits use the socket timeout, so they follow the
+/// system clock whatever `clock` is passed.
+#[derive(Debug)]
+pub struct UdpTransport {
+    socket: UdpSocket,
+}
+
+impl UdpTransport {
+    pub fn new(socket: UdpSocket) -> std::io::Result<Self> {
+        socket.set_write_timeout(Some(Duration::from_secs(15)))?;
+        Ok(Self { socket })
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send_to(&self, packet: &[u8], dest: SocketAddr) -> std::io::Result<()> {
+        let sentlen = self.socket.send_to(packet, dest)?;
+        if sentlen < packet.len() {
+            log::error!("Packet ({} Bytes) not sent in whole", packet.len());
+        } else {
+            log::debug!("Packet ({} Bytes) sent", packet.len());
+        }
+        Ok(())
+    }
+
+    fn recv(&self, _clock: &dyn Clock, timeout: Duration) -> std::io::Result<Vec<u8>> {
+        // a zero timeout would mean blocking forever
+        self.socket.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let mut buf = [0u8; 1500];
+        let (nlen, _remote_addr) = self.socket.recv_from(&mut buf)?;
+        Ok(buf[..nlen].to_vec())
+    }
+}
@@ -0,0 +1,205 @@
+mod common;
+
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddr};
+
+use dhcproto::v4::{DhcpOption, MessageType, OptionCode, UnknownOption};
+use ftth_dhcp::auth::AuthKey;
+use ftth_dhcp::ipv4::{verify_forcerenew, Dhcp4Client, Dhcp4ClientConfig, Dhcp4Outcome};
+use ftth_dhcp::lease::Dhcp4Lease;
+use ftth_dhcp::ipv6::verify_reconfigure;
+
+const KEY: [u8; 16] = *b"0123456789abcdef";
+
+// HMAC-MD5 digests of the packets below, computed with Python's hmac
+// module, for KEY and for an all-zero key.
+const RECONFIGURE_DIGEST: [u8; 16] = [0x25, 0x92, 0x1b, 0x6f, 0x63, 0xfc, 0x4a, 0x94, 0xf5, 0x1a, 0x4c, 0xaa, 0x82, 0xf2, 0x9f, 0x85];
+const RECONFIGURE_DIGEST_ZERO_KEY: [u8; 16] = [0x85, 0x86, 0xab, 0x78, 0x14, 0xb4, 0x10, 0xd0, 0x4e, 0x7d, 0xc2, 0x13, 0xca, 0xad, 0x1e, 0x18];
+const FORCERENEW_DIGEST: [u8; 16] = [0x14, 0x35, 0x37, 0x23, 0xb6, 0x59, 0x17, 0xd0, 0x74, 0xfd, 0xcb, 0x43, 0x3a, 0xe3, 0x8c, 0x53];
+const FORCERENEW_DIGEST_ZERO_KEY: [u8; 16] = [0x93, 0xb1, 0x13, 0xea, 0xd5, 0x0b, 0x19, 0x7f, 0xa5, 0x7c, 0x09, 0x22, 0xda, 0x32, 0xc6, 0x66];
+
+/// A Renew Reconfigure carrying a Reconfigure Key Authentication Protocol
+/// option of `auth_type`, with `digest` as its HMAC.
+fn reconfigure(replay: u64, auth_type: u8, digest: [u8; 16]) -> Vec<u8> {
+    let mut packet = vec![10, 0x12, 0x34, 0x56];
+    packet.extend_from_slice(&[0, 2, 0, 4, 0, 3, 0, 1]);
+    packet.extend_from_slice(&[0, 19, 0, 1, 5]);
+    packet.extend_from_slice(&[0, 11, 0, 28, 3, 1, 0]);
+    packet.extend_from_slice(&replay.to_be_bytes());
+    packet.push(auth_type);
+    packet.extend_from_slice(&digest);
+    packet
+}
+
+#[test]
+fn reconfigure_with_valid_hmac_is_accepted() {
+    assert_eq!(verify_reconfigure(&reconfigure(7, 2, RECONFIGURE_DIGEST), &KEY).unwrap(), 7);
+}
+
+#[test]
+fn reconfigure_with_wrong_key_is_rejected() {
+    assert!(verify_reconfigure(&reconfigure(7, 2, RECONFIGURE_DIGEST_ZERO_KEY), &KEY).is_err());
+}
+
+#[test]
+fn tampered_reconfigure_is_rejected() {
+    let mut packet = reconfigure(7, 2, RECONFIGURE_DIGEST);
+    // Renew becomes Rebind
+    packet[16] = 6;
+    assert!(verify_reconfigure(&packet, &KEY).is_err());
+}
+
+#[test]
+fn reconfigure_without_hmac_is_rejected() {
+    // carries the key itself, as only a Reply may
+    assert!(verify_reconfigure(&reconfigure(7, 1, RECONFIGURE_DIGEST), &KEY).is_err());
+    let packet = reconfigure(7, 2, RECONFIGURE_DIGEST);
+    assert!(verify_reconfigure(&packet[..17], &KEY).is_err());
+}
+
+#[test]
+fn replay_counter_only_moves_forward() {
+    let mut key = AuthKey { key: KEY, replay_counter: 7 };
+    assert!(!key.advance(7));
+    assert!(!key.advance(6));
+    assert!(key.advance(8));
+    assert_eq!(key.replay_counter, 8);
+}
+
+#[test]
+fn debug_leaves_out_the_key() {
+    let debug = format!("{:?}", AuthKey { key: KEY, replay_counter: 7 });
+    assert!(!debug.contains("48, 49"), "{}", debug);
+    assert!(debug.contains("replay_counter: 7"), "{}", debug);
+}
+
+/// A DHCPFORCERENEW carrying a Forcerenew Nonce Protocol option of
+/// `auth_type`, with `digest` as its HMAC.
+fn forcerenew(replay: u64, auth_type: u8, digest: [u8; 16]) -> Vec<u8> {
+    let mut packet = vec![0u8; 236];
+    packet[0] = 2;
+    packet[1] = 1;
+    packet[2] = 6;
+    packet[28..34].copy_from_slice(&[0x02, 0x00, 0x5e, 0x10, 0x20, 0x30]);
+    packet.extend_from_slice(&[99, 130, 83, 99]);
+    packet.extend_from_slice(&[53, 1, 9]);
+    packet.extend_from_slice(&[54, 4, 192, 0, 2, 1]);
+    packet.extend_from_slice(&[90, 28, 3, 1, 0]);
+    packet.extend_from_slice(&replay.to_be_bytes());
+    packet.push(auth_type);
+    packet.extend_from_slice(&digest);
+    packet.push(255);
+    packet
+}
+
+#[test]
+fn forcerenew_with_valid_hmac_is_accepted() {
+    assert_eq!(verify_forcerenew(&forcerenew(3, 2, FORCERENEW_DIGEST), &KEY).unwrap(), 3);
+}
+
+#[test]
+fn forcerenew_hmac_skips_hops_and_giaddr() {
+    // relay agents may change these (RFC 3118 section 5)
+    let mut packet = forcerenew(3, 2, FORCERENEW_DIGEST);
+    packet[3] = 1;
+    packet[24..28].copy_from_slice(&[192, 0, 2, 254]);
+    assert_eq!(verify_forcerenew(&packet, &KEY).unwrap(), 3);
+}
+
+#[test]
+fn forcerenew_with_wrong_nonce_is_rejected() {
+    assert!(verify_forcerenew(&forcerenew(3, 2, FORCERENEW_DIGEST_ZERO_KEY), &KEY).is_err());
+    let mut packet = forcerenew(3, 2, FORCERENEW_DIGEST);
+    packet[16..20].copy_from_slice(&[192, 0, 2, 99]);
+    assert!(verify_forcerenew(&packet, &KEY).is_err());
+}
+
+#[test]
+fn forcerenew_without_hmac_is_rejected() {
+    assert!(verify_forcerenew(&forcerenew(3, 1, FORCERENEW_DIGEST), &KEY).is_err());
+    let packet = forcerenew(3, 2, FORCERENEW_DIGEST);
+    assert!(verify_forcerenew(&packet[..249], &KEY).is_err());
+}
+
+/// An Authentication option handing out KEY as the forcerenew nonce.
+fn nonce_option(replay: u64) -> DhcpOption {
+    let mut data = vec![3, 1, 0];
+    data.extend_from_slice(&replay.to_be_bytes());
+    data.push(1);
+    data.extend_from_slice(&KEY);
+    DhcpOption::Unknown(UnknownOption::new(OptionCode::Unknown(90), data))
+}
+
+/// A server that puts the nonce in the DHCPACK to a DHCPREQUEST in
+/// SELECTING state only, i.e. one without `ciaddr`.
+fn nonce_server() -> std::sync::Arc<common::FakeServer> {
+    common::FakeServer::new(|packet| {
+        let msg = common::decode_dhcp4(packet);
+        match msg.opts().msg_type() {
+            Some(MessageType::Discover) => vec![common::dhcp4_answer(packet, MessageType::Offer, Vec::new())],
+            _ if msg.ciaddr().is_unspecified() => vec![common::dhcp4_answer(packet, MessageType::Ack, vec![nonce_option(1)])],
+            _ => vec![common::dhcp4_answer(packet, MessageType::Ack, Vec::new())],
+        }
+    })
+}
+
+fn nonce_client(server: &std::sync::Arc<common::FakeServer>, config: Dhcp4ClientConfig) -> Dhcp4Client {
+    Dhcp4Client::with_transport(common::MAC, config.with_clock(server.clock.clone()), server.clone())
+}
+
+#[test]
+fn forcerenew_nonce_is_taken_from_the_ack() {
+    let server = nonce_server();
+    let client = nonce_client(&server, Dhcp4ClientConfig::default().with_forcerenew_nonce());
+    let Dhcp4Outcome::Lease(ack) = client.obtain_lease(None).unwrap() else {
+        panic!("no lease");
+    };
+    let nonce = AuthKey { key: KEY, replay_counter: 1 };
+    assert_eq!(ack.forcerenew_nonce, Some(nonce));
+    assert_eq!(client.forcerenew_nonce(Ipv4Addr::new(192, 0, 2, 1)), Some(nonce));
+    assert_eq!(client.forcerenew_nonce(Ipv4Addr::new(192, 0, 2, 2)), None);
+    assert_eq!(Dhcp4Lease::from_response(&ack).unwrap().forcerenew_nonce, Some(nonce));
+}
+
+#[test]
+fn forcerenew_nonce_is_ignored_unless_capable() {
+    let server = nonce_server();
+    let client = nonce_client(&server, Dhcp4ClientConfig::default());
+    let Dhcp4Outcome::Lease(ack) = client.obtain_lease(None).unwrap() else {
+        panic!("no lease");
+    };
+    assert_eq!(ack.forcerenew_nonce, None);
+    assert_eq!(client.forcerenew_nonce(Ipv4Addr::new(192, 0, 2, 1)), None);
+}
+
+#[test]
+fn forcerenew_renews_the_stored_lease() {
+    let server = nonce_server();
+    let config = Dhcp4ClientConfig::default().with_forcerenew_nonce();
+    let Dhcp4Outcome::Lease(ack) = nonce_client(&server, config.clone()).obtain_lease(None).unwrap() else {
+        panic!("no lease");
+    };
+    let lease = Dhcp4Lease::from_response(&ack).unwrap();
+
+    // after a restart, the nonce comes from the stored lease
+    let client = nonce_client(&server, config);
+    let sent = server.sent().len();
+    server.push(forcerenew(3, 2, FORCERENEW_DIGEST));
+    let res = client.handle_forcerenew(&lease).unwrap();
+    assert_eq!(res.msg_type, MessageType::Ack);
+    let renew = &server.sent()[sent];
+    assert_eq!(renew.dest, SocketAddr::from((Ipv4Addr::new(192, 0, 2, 1), 67)));
+    let renew = common::decode_dhcp4(&renew.packet);
+    assert_eq!(renew.opts().msg_type(), Some(MessageType::Request));
+    assert_eq!(renew.ciaddr(), Ipv4Addr::new(192, 0, 2, 10));
+    assert_eq!(client.forcerenew_nonce(lease.server_addr).unwrap().replay_counter, 3);
+
+    // the same message again is a replay
+    server.push(forcerenew(3, 2, FORCERENEW_DIGEST));
+    assert_eq!(client.recv_forcerenew(lease.server_addr).unwrap_err().kind(), ErrorKind::InvalidData);
+    // and a forged one is refused before anything is sent
+    let sent = server.sent().len();
+    server.push(forcerenew(4, 2, FORCERENEW_DIGEST));
+    assert!(client.recv_forcerenew(lease.server_addr).is_err());
+    assert_eq!(server.sent().len(), sent);
+}
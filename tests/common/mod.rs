@@ -0,0 +1,247 @@
+//! Fixtures shared by the integration tests.
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use dhcproto::{Decodable, Decoder, Encodable, Encoder, v4, v6};
+use ftth_dhcp::clock::{Clock, FakeClock};
+use ftth_dhcp::ipv4::Dhcp4Response;
+use ftth_dhcp::ipv6::{Dhcp6Response, PdPrefix};
+use ftth_dhcp::lease::ReceivedAt;
+use ftth_dhcp::options::CustomOptions;
+use ftth_dhcp::transport::Transport;
+use ipnet::Ipv6Net;
+
+/// A DHCPACK for 192.0.2.10/24 from 192.0.2.1, received just now.
+pub fn dhcp4_ack() -> Dhcp4Response {
+    let clock = FakeClock::new();
+    Dhcp4Response {
+        msg_type: v4::MessageType::Ack,
+        client_addr: Some(Ipv4Addr::new(192, 0, 2, 10)),
+        server_addr: Some(Ipv4Addr::new(192, 0, 2, 1)),
+        router_addrs: vec![Ipv4Addr::new(192, 0, 2, 1)],
+        subnet_mask: Some(Ipv4Addr::new(255, 255, 255, 0)),
+        addr_time: 3600,
+        renewal_time: 0,
+        rebind_time: 0,
+        sip_server_addrs: Vec::new(),
+        sip_domain_name: None,
+        sip_main_number: None,
+        sip_add_numbers: Vec::new(),
+        ntt_vendor_info: None,
+        ntt_mac_mismatch: None,
+        static_routes: Vec::new(),
+        classful_routes: Vec::new(),
+        ms_static_routes: Vec::new(),
+        received_at: ReceivedAt::now(&clock),
+        rapid_commit: false,
+        forcerenew_nonce: None,
+        v6only_wait: None,
+        raw_options: Vec::new(),
+        custom_options: CustomOptions::default(),
+    }
+}
+
+/// A delegated prefix such as `"2001:db8::/56"`, valid for two hours.
+pub fn pd(prefix: &str) -> PdPrefix {
+    let prefix: Ipv6Net = prefix.parse().unwrap();
+    PdPrefix {
+        prefix: prefix.addr(),
+        prefix_len: prefix.prefix_len(),
+        preferred_lifetime: 3600,
+        valid_lifetime: 7200,
+        t1: 0,
+        t2: 0,
+        excluded: None,
+    }
+}
+
+/// Like [`pd`], with `excluded` taken out by PD_EXCLUDE.
+pub fn pd_excluding(prefix: &str, excluded: &str) -> PdPrefix {
+    PdPrefix {
+        excluded: Some(excluded.parse().unwrap()),
+        ..pd(prefix)
+    }
+}
+
+/// A Reply delegating `pd`, received just now.
+pub fn dhcp6_reply(pd: Option<PdPrefix>) -> Dhcp6Response {
+    let clock = FakeClock::new();
+    Dhcp6Response {
+        msg_type: v6::MessageType::Reply,
+        client_id: vec![0, 3, 0, 1, 0x02, 0, 0, 0, 0, 0x01],
+        server_id: vec![0, 3, 0, 1, 0x02, 0, 0, 0, 0, 0xfe],
+        pd,
+        na: None,
+        nameserver_addrs: Vec::new(),
+        domain_search_list: Vec::new(),
+        sip_server_addrs: Vec::new(),
+        sntp_server_addrs: Vec::new(),
+        ntt_vendor_info: None,
+        ntt_mac_mismatch: None,
+        aftr_name: None,
+        map_e: None,
+        map_t: None,
+        lw4o6: None,
+        dhcp4o6_servers: None,
+        received_at: ReceivedAt::now(&clock),
+        rapid_commit: false,
+        reconfigure_key: None,
+        server_unicast: None,
+        raw_options: Vec::new(),
+        custom_options: CustomOptions::default(),
+    }
+}
+
+/// A packet a client sent through a [`FakeServer`].
+#[derive(Debug, Clone)]
+pub struct Sent {
+    pub packet: Vec<u8>,
+    pub dest: SocketAddr,
+    /// Time on the server's clock.
+    pub at: Duration,
+}
+
+type Respond = dyn Fn(&[u8]) -> Vec<Vec<u8>> + Send + Sync;
+
+/// A transport standing in for the network: every packet sent is handed
+/// to `respond`, and what it returns is received next. Waiting when
+/// nothing is left lets the fake clock run out the timeout.
+pub struct FakeServer {
+    pub clock: Arc<FakeClock>,
+    respond: Box<Respond>,
+    sent: Mutex<Vec<Sent>>,
+    queue: Mutex<VecDeque<Vec<u8>>>,
+}
+
+impl std::fmt::Debug for FakeServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FakeServer").finish_non_exhaustive()
+    }
+}
+
+impl FakeServer {
+    pub fn new(respond: impl Fn(&[u8]) -> Vec<Vec<u8>> + Send + Sync + 'static) -> Arc<Self> {
+        Arc::new(Self {
+            clock: Arc::new(FakeClock::new()),
+            respond: Box::new(respond),
+            sent: Mutex::new(Vec::new()),
+            queue: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// A server that never answers.
+    pub fn silent() -> Arc<Self> {
+        Self::new(|_| Vec::new())
+    }
+
+    /// Queues a packet nobody asked for, such as a DHCPFORCERENEW.
+    pub fn push(&self, packet: Vec<u8>) {
+        self.queue.lock().unwrap().push_back(packet);
+    }
+
+    pub fn sent(&self) -> Vec<Sent> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+impl Transport for FakeServer {
+    fn send_to(&self, packet: &[u8], dest: SocketAddr) -> std::io::Result<()> {
+        let at = self.clock.now().since_boot();
+        self.sent.lock().unwrap().push(Sent { packet: packet.to_vec(), dest, at });
+        let replies = (self.respond)(packet);
+        self.queue.lock().unwrap().extend(replies);
+        Ok(())
+    }
+
+    fn recv(&self, clock: &dyn Clock, timeout: Duration) -> std::io::Result<Vec<u8>> {
+        if let Some(packet) = self.queue.lock().unwrap().pop_front() {
+            return Ok(packet);
+        }
+        clock.sleep(timeout);
+        Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "no packet"))
+    }
+}
+
+pub const MAC: [u8; 6] = [0x02, 0x00, 0x5e, 0x10, 0x20, 0x30];
+pub const SERVER_DUID: [u8; 10] = [0, 3, 0, 1, 0x02, 0, 0, 0, 0, 0xfe];
+
+pub fn decode_dhcp4(packet: &[u8]) -> v4::Message {
+    v4::Message::decode(&mut Decoder::new(packet)).unwrap()
+}
+
+pub fn encode_dhcp4(msg: &v4::Message) -> Vec<u8> {
+    let mut buf = Vec::new();
+    msg.encode(&mut Encoder::new(&mut buf)).unwrap();
+    buf
+}
+
+/// A reply of `msg_type` to the DHCPv4 `request`, offering 192.0.2.10
+/// from 192.0.2.1 for an hour, with `extra` options.
+pub fn dhcp4_answer(request: &[u8], msg_type: v4::MessageType, extra: Vec<v4::DhcpOption>) -> Vec<u8> {
+    let request = decode_dhcp4(request);
+    let server = Ipv4Addr::new(192, 0, 2, 1);
+    let mut msg = v4::Message::new_with_id(
+        request.xid(),
+        Ipv4Addr::UNSPECIFIED,
+        Ipv4Addr::new(192, 0, 2, 10),
+        Ipv4Addr::UNSPECIFIED,
+        Ipv4Addr::UNSPECIFIED,
+        &request.chaddr()[..6],
+    );
+    msg.set_opcode(v4::Opcode::BootReply);
+    msg.opts_mut().insert(v4::DhcpOption::MessageType(msg_type));
+    msg.opts_mut().insert(v4::DhcpOption::ServerIdentifier(server));
+    msg.opts_mut().insert(v4::DhcpOption::AddressLeaseTime(3600));
+    msg.opts_mut().insert(v4::DhcpOption::SubnetMask(Ipv4Addr::new(255, 255, 255, 0)));
+    msg.opts_mut().insert(v4::DhcpOption::Router(vec![server]));
+    for opt in extra {
+        msg.opts_mut().insert(opt);
+    }
+    encode_dhcp4(&msg)
+}
+
+pub fn decode_dhcp6(packet: &[u8]) -> v6::Message {
+    v6::Message::decode(&mut Decoder::new(packet)).unwrap()
+}
+
+pub fn encode_dhcp6(msg: &v6::Message) -> Vec<u8> {
+    let mut buf = Vec::new();
+    msg.encode(&mut Encoder::new(&mut buf)).unwrap();
+    buf
+}
+
+/// A reply of `msg_type` to the DHCPv6 `request` from [`SERVER_DUID`],
+/// delegating `prefix` in the IA_PD the request asked for, if any.
+pub fn dhcp6_answer(request: &[u8], msg_type: v6::MessageType, prefix: Option<&str>) -> Vec<u8> {
+    dhcp6_answer_with(request, msg_type, prefix, Vec::new())
+}
+
+/// Like [`dhcp6_answer`], with `extra` options.
+pub fn dhcp6_answer_with(request: &[u8], msg_type: v6::MessageType, prefix: Option<&str>, extra: Vec<v6::DhcpOption>) -> Vec<u8> {
+    let request = decode_dhcp6(request);
+    let mut msg = v6::Message::new_with_id(msg_type, request.xid());
+    if let Some(client_id) = request.opts().get(v6::OptionCode::ClientId) {
+        msg.opts_mut().insert(client_id.clone());
+    }
+    msg.opts_mut().insert(v6::DhcpOption::ServerId(SERVER_DUID.to_vec()));
+    if let (Some(prefix), Some(v6::DhcpOption::IAPD(iapd))) = (prefix, request.opts().get(v6::OptionCode::IAPD)) {
+        let prefix: Ipv6Net = prefix.parse().unwrap();
+        let mut opts = v6::DhcpOptions::new();
+        opts.insert(v6::DhcpOption::IAPrefix(v6::IAPrefix {
+            preferred_lifetime: 3600,
+            valid_lifetime: 7200,
+            prefix_len: prefix.prefix_len(),
+            prefix_ip: prefix.addr(),
+            opts: v6::DhcpOptions::new(),
+        }));
+        msg.opts_mut().insert(v6::DhcpOption::IAPD(v6::IAPD { id: iapd.id, t1: 1800, t2: 2880, opts }));
+    }
+    for opt in extra {
+        msg.opts_mut().insert(opt);
+    }
+    encode_dhcp6(&msg)
+}
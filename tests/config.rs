@@ -0,0 +1,81 @@
+mod common;
+
+use std::time::Duration;
+
+use ftth_dhcp::ipv4::{Dhcp4Client, Dhcp4ClientConfig, Dhcp4Outcome};
+use ftth_dhcp::ipv6::{Dhcp6Client, Dhcp6ClientConfig};
+use ftth_dhcp::lease::{Dhcp4Lease, Dhcp6Lease};
+use ftth_dhcp::profile::{GenericProfile, NttNgnProfile, ProvisioningProfile};
+
+#[test]
+fn user_classes_must_fit_their_length_field() {
+    assert!(Dhcp4ClientConfig::default().with_user_classes(&[b"class", &[0u8; 255]]).is_ok());
+    assert!(Dhcp4ClientConfig::default().with_user_classes(&[&[0u8; 256]]).is_err());
+    assert!(Dhcp4ClientConfig::default().with_user_classes(&[b""]).is_err());
+    assert!(Dhcp6ClientConfig::default().with_user_classes(&[&[0u8; 65535]]).is_ok());
+    assert!(Dhcp6ClientConfig::default().with_user_classes(&[&[0u8; 65536]]).is_err());
+}
+
+#[test]
+fn generic_client_id_carries_hardware_type() {
+    let mac = [0x02, 0, 0, 0, 0, 0x01];
+    assert_eq!(GenericProfile.dhcp4_client_id(mac), [0x01, 0x02, 0, 0, 0, 0, 0x01]);
+    assert_eq!(NttNgnProfile.dhcp4_client_id(mac), mac);
+}
+
+#[test]
+fn startup_rebind_is_bounded() {
+    use dhcproto::v6::MessageType;
+
+    let server = common::FakeServer::new(|packet| match common::decode_dhcp6(packet).msg_type() {
+        MessageType::Solicit => vec![common::dhcp6_answer(packet, MessageType::Advertise, Some("2001:db8:1::/56"))],
+        MessageType::Request => vec![common::dhcp6_answer(packet, MessageType::Reply, Some("2001:db8:1::/56"))],
+        _ => Vec::new(),
+    });
+    let config = Dhcp6ClientConfig::default()
+        .with_clock(server.clock.clone())
+        .with_rebind_timeout(Duration::from_secs(3));
+    let client = Dhcp6Client::with_transport("fe80::1".parse().unwrap(), [0x02, 0, 0, 0, 0, 0x01], config, server.clone()).unwrap();
+    let previous = Dhcp6Lease::from_response(&common::dhcp6_reply(Some(common::pd("2001:db8::/56"))), 1).unwrap();
+
+    let res = client.obtain_pd(1, Some(&previous)).unwrap();
+    assert_eq!(res.pd.unwrap().prefix, "2001:db8:1::".parse::<std::net::Ipv6Addr>().unwrap());
+    let sent: Vec<_> = server.sent().iter().map(|s| (common::decode_dhcp6(&s.packet).msg_type(), s.at)).collect();
+    assert_eq!(sent[0], (MessageType::Rebind, Duration::ZERO));
+    // the unanswered Rebind gives up after 3 seconds, not the 10 of its IRT
+    let solicit = sent.iter().find(|(t, _)| *t == MessageType::Solicit).unwrap();
+    assert_eq!(solicit.1, Duration::from_secs(3));
+}
+
+#[test]
+fn init_reboot_is_bounded() {
+    use dhcproto::v4::{DhcpOption, MessageType, OptionCode};
+
+    // answers DHCPDISCOVER and the DHCPREQUEST that follows, which names
+    // the server, but not the INIT-REBOOT one, which does not
+    let server = common::FakeServer::new(|packet| {
+        let msg = common::decode_dhcp4(packet);
+        match msg.opts().msg_type() {
+            Some(MessageType::Discover) => vec![common::dhcp4_answer(packet, MessageType::Offer, Vec::new())],
+            Some(MessageType::Request) if msg.opts().get(OptionCode::ServerIdentifier).is_some() => {
+                vec![common::dhcp4_answer(packet, MessageType::Ack, vec![DhcpOption::AddressLeaseTime(7200)])]
+            },
+            _ => Vec::new(),
+        }
+    });
+    let config = Dhcp4ClientConfig::default().with_clock(server.clock.clone());
+    let client = Dhcp4Client::with_transport(common::MAC, config, server.clone());
+    let previous = Dhcp4Lease::from_response(&common::dhcp4_ack()).unwrap();
+
+    let Dhcp4Outcome::Lease(ack) = client.obtain_lease(Some(&previous)).unwrap() else {
+        panic!("no lease");
+    };
+    assert_eq!(ack.addr_time, 7200);
+    let sent: Vec<_> = server.sent().iter().map(|s| (common::decode_dhcp4(&s.packet).opts().msg_type(), s.at)).collect();
+    let reboots = sent.iter().take_while(|(t, _)| *t == Some(MessageType::Request)).count();
+    assert_eq!(reboots, 5);
+    // 4 + 8 + 16 + 32 + 64 seconds, give or take the randomization
+    let discover = sent[reboots];
+    assert_eq!(discover.0, Some(MessageType::Discover));
+    assert!(discover.1 > Duration::from_secs(100) && discover.1 < Duration::from_secs(150), "{:?}", discover.1);
+}
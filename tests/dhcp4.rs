@@ -0,0 +1,93 @@
+mod common;
+
+use std::sync::Arc;
+
+use dhcproto::v4::{DhcpOption, MessageType};
+use ftth_dhcp::ipv4::{Dhcp4Client, Dhcp4ClientConfig, Dhcp4Outcome};
+
+fn client(server: &Arc<common::FakeServer>, config: Dhcp4ClientConfig) -> Dhcp4Client {
+    Dhcp4Client::with_transport(common::MAC, config.with_clock(server.clock.clone()), server.clone())
+}
+
+fn msg_types(server: &common::FakeServer) -> Vec<MessageType> {
+    server.sent().iter().map(|s| common::decode_dhcp4(&s.packet).opts().msg_type().unwrap()).collect()
+}
+
+#[test]
+fn rapid_commit_ack_ends_the_discover() {
+    let server = common::FakeServer::new(|packet| {
+        vec![common::dhcp4_answer(packet, MessageType::Ack, vec![DhcpOption::RapidCommit])]
+    });
+    let client = client(&server, Dhcp4ClientConfig::default().with_rapid_commit());
+    let Dhcp4Outcome::Lease(ack) = client.obtain_lease(None).unwrap() else {
+        panic!("no lease");
+    };
+    assert_eq!(ack.msg_type, MessageType::Ack);
+    assert!(ack.rapid_commit);
+    assert_eq!(msg_types(&server), [MessageType::Discover]);
+    assert!(common::decode_dhcp4(&server.sent()[0].packet).opts().get(dhcproto::v4::OptionCode::RapidCommit).is_some());
+}
+
+#[test]
+fn ack_without_rapid_commit_is_not_taken_for_an_offer() {
+    // a DHCPACK to a DHCPDISCOVER must carry Rapid Commit (RFC 4039
+    // section 4); this one is dropped and the OFFER after it taken
+    let server = common::FakeServer::new(|packet| match common::decode_dhcp4(packet).opts().msg_type() {
+        Some(MessageType::Discover) => vec![
+            common::dhcp4_answer(packet, MessageType::Ack, Vec::new()),
+            common::dhcp4_answer(packet, MessageType::Offer, Vec::new()),
+        ],
+        _ => vec![common::dhcp4_answer(packet, MessageType::Ack, Vec::new())],
+    });
+    let client = client(&server, Dhcp4ClientConfig::default().with_rapid_commit());
+    let Dhcp4Outcome::Lease(ack) = client.obtain_lease(None).unwrap() else {
+        panic!("no lease");
+    };
+    assert!(!ack.rapid_commit);
+    assert_eq!(msg_types(&server), [MessageType::Discover, MessageType::Request]);
+}
+
+#[test]
+fn rapid_commit_ack_is_ignored_unless_asked_for() {
+    let server = common::FakeServer::new(|packet| match common::decode_dhcp4(packet).opts().msg_type() {
+        Some(MessageType::Discover) => vec![
+            common::dhcp4_answer(packet, MessageType::Ack, vec![DhcpOption::RapidCommit]),
+            common::dhcp4_answer(packet, MessageType::Offer, Vec::new()),
+        ],
+        _ => vec![common::dhcp4_answer(packet, MessageType::Ack, Vec::new())],
+    });
+    let client = client(&server, Dhcp4ClientConfig::default());
+    assert!(matches!(client.obtain_lease(None).unwrap(), Dhcp4Outcome::Lease(_)));
+    assert_eq!(msg_types(&server), [MessageType::Discover, MessageType::Request]);
+}
+
+/// Runs a DISCOVER against a server whose OFFER carries IPv6-Only
+/// Preferred with `wait` seconds, if any.
+fn v6only_outcome(wait: Option<u32>) -> Dhcp4Outcome {
+    let server = common::FakeServer::new(move |packet| {
+        let extra = wait.map(|wait| {
+            let code = dhcproto::v4::OptionCode::Unknown(108);
+            DhcpOption::Unknown(dhcproto::v4::UnknownOption::new(code, wait.to_be_bytes().to_vec()))
+        });
+        let msg_type = match common::decode_dhcp4(packet).opts().msg_type() {
+            Some(MessageType::Discover) => MessageType::Offer,
+            _ => MessageType::Ack,
+        };
+        vec![common::dhcp4_answer(packet, msg_type, extra.into_iter().collect())]
+    });
+    let client = client(&server, Dhcp4ClientConfig::default().with_ipv6_only_preferred());
+    client.obtain_lease(None).unwrap()
+}
+
+#[test]
+fn v6only_wait_is_clamped_to_the_minimum() {
+    use std::time::Duration;
+
+    use ftth_dhcp::ipv4::MIN_V6ONLY_WAIT;
+
+    assert_eq!(MIN_V6ONLY_WAIT, Duration::from_secs(300));
+    assert!(matches!(v6only_outcome(Some(60)), Dhcp4Outcome::Ipv6Only(wait) if wait == MIN_V6ONLY_WAIT));
+    assert!(matches!(v6only_outcome(Some(0)), Dhcp4Outcome::Ipv6Only(wait) if wait == MIN_V6ONLY_WAIT));
+    assert!(matches!(v6only_outcome(Some(1800)), Dhcp4Outcome::Ipv6Only(wait) if wait == Duration::from_secs(1800)));
+    assert!(matches!(v6only_outcome(None), Dhcp4Outcome::Lease(ack) if ack.v6only_wait.is_none()));
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use dhcproto::{v4, v6};
+use ftth_dhcp::dhcp4o6::{decode_response, decode_servers, encode_query};
+use ftth_dhcp::ipv4::{Dhcp4Client, Dhcp4ClientConfig, Dhcp4Outcome};
+use ftth_dhcp::ipv6::{Dhcp6Client, Dhcp6ClientConfig};
+
+#[test]
+fn query_wraps_dhcp4_message() {
+    let dhcp4 = [1u8, 1, 6, 0, 0xde, 0xad, 0xbe, 0xef];
+    let query = encode_query(&dhcp4, false).unwrap();
+    assert_eq!(&query[..8], &[20, 0, 0, 0, 0, 87, 0, 8]);
+    assert_eq!(&query[8..], &dhcp4);
+    let query = encode_query(&dhcp4, true).unwrap();
+    assert_eq!(&query[..4], &[20, 0x80, 0, 0]);
+}
+
+#[test]
+fn response_unwraps_dhcp4_message() {
+    let dhcp4 = [2u8, 1, 6, 0, 0xde, 0xad, 0xbe, 0xef];
+    let mut packet = vec![21, 0, 0, 0, 0, 87, 0, 8];
+    packet.extend_from_slice(&dhcp4);
+    assert_eq!(decode_response(&packet).unwrap(), dhcp4);
+
+    packet[0] = 20;
+    assert!(decode_response(&packet).is_err());
+    assert!(decode_response(&[21, 0, 0, 0]).is_err());
+}
+
+#[test]
+fn server_addresses() {
+    let addr: std::net::Ipv6Addr = "2001:db8::4".parse().unwrap();
+    assert_eq!(decode_servers(&addr.octets()).unwrap(), [addr]);
+    assert!(decode_servers(&[]).unwrap().is_empty());
+    assert!(decode_servers(&[0; 15]).is_err());
+}
+
+/// A DHCPV4-RESPONSE carrying `dhcp4`.
+fn response(dhcp4: Vec<u8>) -> Vec<u8> {
+    let mut msg = v6::Message::new_with_id(v6::MessageType::DHCPv4Response, [0, 0, 0]);
+    msg.opts_mut().insert(v6::DhcpOption::Unknown(v6::UnknownOption::new(v6::OptionCode::Dhcpv4Msg, dhcp4)));
+    common::encode_dhcp6(&msg)
+}
+
+/// The DHCPv4 message in a DHCPV4-QUERY.
+fn query_msg(packet: &[u8]) -> Vec<u8> {
+    match common::decode_dhcp6(packet).opts().get(v6::OptionCode::Dhcpv4Msg) {
+        Some(v6::DhcpOption::Unknown(opt)) => opt.data().to_vec(),
+        _ => panic!("no DHCPv4 message"),
+    }
+}
+
+#[test]
+fn dhcp4_and_dhcp6_clients_share_the_socket() {
+    // every DHCPV4-RESPONSE comes after a Reply to the last DHCPv6 message
+    let last_dhcp6 = Mutex::new(Vec::new());
+    let server = common::FakeServer::new(move |packet| {
+        if common::decode_dhcp6(packet).msg_type() != v6::MessageType::DHCPv4Query {
+            *last_dhcp6.lock().unwrap() = packet.to_vec();
+            return Vec::new();
+        }
+        let dhcp4 = query_msg(packet);
+        let msg_type = match common::decode_dhcp4(&dhcp4).opts().msg_type() {
+            Some(v4::MessageType::Discover) => v4::MessageType::Offer,
+            _ => v4::MessageType::Ack,
+        };
+        let reply = common::dhcp6_answer(&last_dhcp6.lock().unwrap(), v6::MessageType::Reply, None);
+        vec![reply, response(common::dhcp4_answer(&dhcp4, msg_type, Vec::new()))]
+    });
+    let config = Dhcp6ClientConfig::default().with_clock(server.clock.clone());
+    let dhcp6 = Dhcp6Client::with_transport("fe80::1".parse().unwrap(), common::MAC, config, server.clone()).unwrap();
+    dhcp6.information_request(Duration::ZERO).unwrap();
+
+    let config = Dhcp4ClientConfig::default().with_clock(server.clock.clone());
+    let server_addr: Ipv6Addr = "2001:db8::4".parse().unwrap();
+    let dhcp4 = Dhcp4Client::over_dhcp6(&dhcp6, config, vec![server_addr]);
+    let Dhcp4Outcome::Lease(ack) = dhcp4.obtain_lease(None).unwrap() else {
+        panic!("no lease");
+    };
+    assert_eq!(ack.client_addr, Some(Ipv4Addr::new(192, 0, 2, 10)));
+    let queries = &server.sent()[1..];
+    assert_eq!(queries.len(), 2);
+    for query in queries {
+        assert_eq!(query.dest, SocketAddr::from((server_addr, Dhcp6Client::SERVER_PORT)));
+        assert_eq!(query.packet[0], u8::from(v6::MessageType::DHCPv4Query));
+    }
+
+    // the Replies read meanwhile were kept for the DHCPv6 client
+    assert_eq!(dhcp6.recv(v6::MessageType::Reply).unwrap().server_id, common::SERVER_DUID);
+    assert_eq!(dhcp6.recv(v6::MessageType::Reply).unwrap().server_id, common::SERVER_DUID);
+}
@@ -0,0 +1,165 @@
+mod common;
+
+use std::net::{Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use dhcproto::v6::{DhcpOption, DhcpOptions, IAAddr, MessageType, OptionCode, Status, StatusCode, IANA};
+use ftth_dhcp::clock::Clock;
+use ftth_dhcp::ipv6::{BindingCheck, Dhcp6Client, Dhcp6ClientConfig};
+
+fn client(server: &Arc<common::FakeServer>) -> Dhcp6Client {
+    let config = Dhcp6ClientConfig::default().with_clock(server.clock.clone());
+    Dhcp6Client::with_transport("fe80::1".parse().unwrap(), common::MAC, config, server.clone()).unwrap()
+}
+
+/// Message types sent, with where they went.
+fn sent(server: &common::FakeServer) -> Vec<(MessageType, SocketAddr)> {
+    server.sent().iter().map(|s| (common::decode_dhcp6(&s.packet).msg_type(), s.dest)).collect()
+}
+
+/// A server advertising Server Unicast with `addr`. It refuses the first
+/// `refused` Requests with UseMulticast.
+fn unicast_server(addr: &'static str, refused: usize) -> Arc<common::FakeServer> {
+    let addr: Ipv6Addr = addr.parse().unwrap();
+    let requests = AtomicUsize::new(0);
+    common::FakeServer::new(move |packet| {
+        let prefix = Some("2001:db8::/56");
+        match common::decode_dhcp6(packet).msg_type() {
+            MessageType::Solicit => vec![common::dhcp6_answer_with(packet, MessageType::Advertise, prefix, vec![DhcpOption::ServerUnicast(addr)])],
+            _ if requests.fetch_add(1, Ordering::Relaxed) < refused => {
+                let status = DhcpOption::StatusCode(StatusCode { status: Status::UseMulticast, msg: String::new() });
+                vec![common::dhcp6_answer_with(packet, MessageType::Reply, None, vec![status])]
+            },
+            _ => vec![common::dhcp6_answer(packet, MessageType::Reply, prefix)],
+        }
+    })
+}
+
+fn to(addr: &str) -> SocketAddr {
+    (addr.parse::<Ipv6Addr>().unwrap(), Dhcp6Client::SERVER_PORT).into()
+}
+
+#[test]
+fn request_is_unicast_to_a_link_local_server() {
+    let server = unicast_server("fe80::fe", 0);
+    client(&server).obtain_pd(1, None).unwrap();
+    assert_eq!(sent(&server), [(MessageType::Solicit, to("ff02::1:2")), (MessageType::Request, to("fe80::fe"))]);
+}
+
+#[test]
+fn request_to_a_global_server_is_multicast() {
+    // the link-local source could not reach it
+    let server = unicast_server("2001:db8::fe", 0);
+    client(&server).obtain_pd(1, None).unwrap();
+    assert_eq!(sent(&server), [(MessageType::Solicit, to("ff02::1:2")), (MessageType::Request, to("ff02::1:2"))]);
+}
+
+#[test]
+fn use_multicast_sends_the_request_again_by_multicast() {
+    let server = unicast_server("fe80::fe", 1);
+    let client = client(&server);
+    let res = client.obtain_pd(1, None).unwrap();
+    assert!(res.pd.is_some());
+    assert_eq!(sent(&server), [
+        (MessageType::Solicit, to("ff02::1:2")),
+        (MessageType::Request, to("fe80::fe")),
+        (MessageType::Request, to("ff02::1:2")),
+    ]);
+    let xids: Vec<_> = server.sent()[1..].iter().map(|s| common::decode_dhcp6(&s.packet).xid()).collect();
+    assert_eq!(xids[0], xids[1]);
+
+    // and the server is not unicast to again
+    client.renew_pd(Duration::ZERO, 1, common::SERVER_DUID.to_vec(), &res.pd.unwrap()).unwrap();
+    assert_eq!(sent(&server)[3], (MessageType::Renew, to("ff02::1:2")));
+}
+
+const NA_ADDR: &str = "2001:db8:ffff::10";
+
+/// An IA_NA like the one in `request`, assigning [`NA_ADDR`].
+fn ia_na(request: &[u8]) -> DhcpOption {
+    let Some(DhcpOption::IANA(ia_na)) = common::decode_dhcp6(request).opts().get(OptionCode::IANA).cloned() else {
+        panic!("no IA_NA");
+    };
+    let mut opts = DhcpOptions::new();
+    opts.insert(DhcpOption::IAAddr(IAAddr { addr: NA_ADDR.parse().unwrap(), preferred_life: 3600, valid_life: 7200, opts: DhcpOptions::new() }));
+    DhcpOption::IANA(IANA { id: ia_na.id, t1: 1800, t2: 2880, opts })
+}
+
+/// A server assigning [`NA_ADDR`], which answers Confirm with `confirm`,
+/// or not at all.
+fn na_server(confirm: Option<Status>) -> Arc<common::FakeServer> {
+    common::FakeServer::new(move |packet| {
+        let prefix = Some("2001:db8::/56");
+        match common::decode_dhcp6(packet).msg_type() {
+            MessageType::Solicit => vec![common::dhcp6_answer_with(packet, MessageType::Advertise, prefix, vec![ia_na(packet)])],
+            MessageType::Confirm => match confirm {
+                Some(status) => vec![common::dhcp6_answer_with(packet, MessageType::Reply, None, vec![
+                    DhcpOption::StatusCode(StatusCode { status, msg: String::new() }),
+                ])],
+                None => Vec::new(),
+            },
+            _ => vec![common::dhcp6_answer_with(packet, MessageType::Reply, prefix, vec![ia_na(packet)])],
+        }
+    })
+}
+
+fn na_client(server: &Arc<common::FakeServer>) -> Dhcp6Client {
+    let config = Dhcp6ClientConfig::default().with_clock(server.clock.clone()).with_ia_na();
+    Dhcp6Client::with_transport("fe80::1".parse().unwrap(), common::MAC, config, server.clone()).unwrap()
+}
+
+/// The address in the IA_NA of a message sent.
+fn sent_na_addr(packet: &[u8]) -> Option<Ipv6Addr> {
+    match common::decode_dhcp6(packet).opts().get(OptionCode::IANA) {
+        Some(DhcpOption::IANA(ia_na)) => ia_na.opts.iter().find_map(|opt| match opt {
+            DhcpOption::IAAddr(addr) => Some(addr.addr),
+            _ => None,
+        }),
+        _ => panic!("no IA_NA"),
+    }
+}
+
+#[test]
+fn ia_na_address_is_requested() {
+    let server = na_server(None);
+    let res = na_client(&server).obtain_pd(1, None).unwrap();
+    let na = res.na.unwrap();
+    assert_eq!((na.ia_id, na.addr, na.valid_lifetime), (1, NA_ADDR.parse().unwrap(), 7200));
+    assert!(res.pd.is_some());
+    let sent = server.sent();
+    assert_eq!(sent_na_addr(&sent[0].packet), None);
+    assert_eq!(sent_na_addr(&sent[1].packet), Some(NA_ADDR.parse().unwrap()));
+    // and nothing is asked for without with_ia_na
+    let server = common::FakeServer::silent();
+    client(&server).solicit_pd(Duration::ZERO, 1).unwrap();
+    assert!(common::decode_dhcp6(&server.sent()[0].packet).opts().get(OptionCode::IANA).is_none());
+}
+
+#[test]
+fn confirm_checks_the_address_after_a_link_change() {
+    for (confirm, valid) in [(Status::Success, true), (Status::NotOnLink, false)] {
+        let server = na_server(Some(confirm));
+        let client = na_client(&server);
+        let res = client.obtain_pd(1, None).unwrap();
+        let check = client.check_na_on_link_change(&res).unwrap();
+        assert_eq!(matches!(check, BindingCheck::Valid(None)), valid, "{:?}", confirm);
+        assert_eq!(matches!(check, BindingCheck::Invalid), !valid, "{:?}", confirm);
+        let confirm = server.sent().last().unwrap().packet.clone();
+        assert_eq!(common::decode_dhcp6(&confirm).msg_type(), MessageType::Confirm);
+        assert_eq!(sent_na_addr(&confirm), Some(NA_ADDR.parse().unwrap()));
+    }
+}
+
+#[test]
+fn unanswered_confirm_gives_up_after_cnf_max_rd() {
+    let server = na_server(None);
+    let client = na_client(&server);
+    let res = client.obtain_pd(1, None).unwrap();
+    let start = server.clock.now();
+    assert!(matches!(client.check_na_on_link_change(&res).unwrap(), BindingCheck::NoReply));
+    assert_eq!(server.clock.now() - start, Duration::from_secs(10));
+    let confirms = server.sent().iter().filter(|s| common::decode_dhcp6(&s.packet).msg_type() == MessageType::Confirm).count();
+    assert!(confirms > 1, "{}", confirms);
+}
@@ -0,0 +1,101 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use ftth_dhcp::identity::{iaid_from_interface, ClientIdentity, Duid};
+use ftth_dhcp::profile::{GenericProfile, ProvisioningProfile};
+
+const MAC: [u8; 6] = [0x02, 0x00, 0x5e, 0x10, 0x20, 0x30];
+
+#[test]
+fn duid_round_trip() {
+    let duids = [
+        Duid::llt_from_mac(MAC, UNIX_EPOCH + Duration::from_secs(946_684_800 + 1000)),
+        Duid::en(32473, b"router-1"),
+        Duid::ll_from_mac(MAC),
+        Duid::Uuid([0x5a; 16]),
+        Duid::Other(vec![0, 9, 1, 2, 3]),
+    ];
+    for duid in duids {
+        assert_eq!(Duid::from_bytes(&duid.to_bytes()).unwrap(), duid);
+    }
+}
+
+#[test]
+fn duid_known_bytes() {
+    assert_eq!(Duid::ll_from_mac(MAC).to_bytes(), [0, 3, 0, 1, 0x02, 0x00, 0x5e, 0x10, 0x20, 0x30]);
+    let llt = Duid::llt_from_mac(MAC, UNIX_EPOCH + Duration::from_secs(946_684_800 + 0x0102_0304));
+    assert_eq!(&llt.to_bytes()[..8], [0, 1, 0, 1, 1, 2, 3, 4]);
+    assert_eq!(Duid::en(9, &[0xaa]).to_bytes(), [0, 2, 0, 0, 0, 9, 0xaa]);
+    assert_eq!(GenericProfile.dhcp6_duid(MAC), Duid::ll_from_mac(MAC).to_bytes());
+}
+
+#[test]
+fn malformed_duids_are_rejected() {
+    // too short, too long
+    assert!(Duid::from_bytes(&[0, 3]).is_err());
+    assert!(Duid::from_bytes(&[0u8; 131]).is_err());
+    // known types without a body
+    assert!(Duid::from_bytes(&[0, 1, 0, 1, 0, 0, 0, 0]).is_err());
+    assert!(Duid::from_bytes(&[0, 2, 0, 0, 0, 9]).is_err());
+    assert!(Duid::from_bytes(&[0, 3, 0, 1]).is_err());
+    // UUID of the wrong size
+    assert!(Duid::from_bytes(&[0, 4, 1, 2, 3]).is_err());
+    assert!(Duid::from_bytes(&[0u8, 4].iter().chain(&[0u8; 17]).copied().collect::<Vec<_>>()).is_err());
+}
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("ftth-dhcp-test-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn identity_is_created_once() {
+    let path = temp_path("identity");
+    let identity = ClientIdentity::load_or_create(&path, 2, MAC).unwrap();
+    assert_eq!(identity.iaid, iaid_from_interface(2, MAC));
+    // a different interface index later does not change the stored IAID
+    assert_eq!(ClientIdentity::load_or_create(&path, 3, MAC).unwrap(), identity);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn duid_only_file_keeps_its_duid() {
+    let path = temp_path("duid-only");
+    let duid = Duid::en(32473, b"router-1");
+    duid.store(&path).unwrap();
+    let identity = ClientIdentity::load_or_create(&path, 2, MAC).unwrap();
+    assert_eq!(identity.duid, duid);
+    assert_eq!(ClientIdentity::load(&path).unwrap(), identity);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn corrupt_identity_is_not_overwritten() {
+    let path = temp_path("corrupt");
+    std::fs::write(&path, "duid=zz\niaid=1\n").unwrap();
+    let e = ClientIdentity::load_or_create(&path, 2, MAC).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "duid=zz\niaid=1\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn dhcp4_client_id_known_bytes() {
+    use ftth_dhcp::ipv4::Dhcp4ClientId;
+    use ftth_dhcp::profile::NttNgnProfile;
+
+    // NTT NGN: the bare MAC address, without a type byte
+    assert_eq!(Dhcp4ClientId::Custom(MAC.to_vec()).to_bytes(MAC), MAC);
+    assert_eq!(NttNgnProfile.dhcp4_client_id(MAC), MAC);
+
+    assert_eq!(Dhcp4ClientId::HardwareAddr.to_bytes(MAC), [1, 0x02, 0x00, 0x5e, 0x10, 0x20, 0x30]);
+    assert_eq!(GenericProfile.dhcp4_client_id(MAC), Dhcp4ClientId::HardwareAddr.to_bytes(MAC));
+
+    // RFC 4361 section 6.1: type 255, IAID, DUID
+    let id = Dhcp4ClientId::NodeSpecific { iaid: 0x0a0b0c0d, duid: Duid::ll_from_mac(MAC) };
+    assert_eq!(id.to_bytes([0; 6]), [
+        255,
+        0x0a, 0x0b, 0x0c, 0x0d,
+        0, 3, 0, 1, 0x02, 0x00, 0x5e, 0x10, 0x20, 0x30,
+    ]);
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use dhcproto::v4::MessageType;
+use dhcproto::v6;
+use ftth_dhcp::auth::AuthKey;
+use ftth_dhcp::clock::{Clock, FakeClock};
+use ftth_dhcp::lease::{Dhcp4Lease, Dhcp6Lease, LeaseState, LeaseStore, ReceivedAt};
+
+#[test]
+fn dhcp4_lease_only_from_ack() {
+    let mut res = common::dhcp4_ack();
+    assert_eq!(Dhcp4Lease::from_response(&res).unwrap().client_addr, res.client_addr.unwrap());
+    res.msg_type = MessageType::Offer;
+    assert!(Dhcp4Lease::from_response(&res).is_err());
+}
+
+#[test]
+fn dhcp6_lease_only_from_reply() {
+    let mut res = common::dhcp6_reply(Some(common::pd("2001:db8:1200::/56")));
+    let lease = Dhcp6Lease::from_response(&res, 1).unwrap();
+    assert_eq!((lease.prefix, lease.prefix_len), ("2001:db8:1200::".parse().unwrap(), 56));
+    res.msg_type = v6::MessageType::Advertise;
+    assert!(Dhcp6Lease::from_response(&res, 1).is_err());
+}
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("ftth-dhcp-test-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn stored_lease_ages_by_wall_clock() {
+    let clock = Arc::new(FakeClock::new());
+    let store = LeaseStore::new(&temp_dir("lease-age"), "eth0").with_clock(clock.clone());
+    let mut lease = Dhcp4Lease::from_response(&common::dhcp4_ack()).unwrap();
+    lease.times.received_at = ReceivedAt::now(clock.as_ref());
+    store.store_dhcp4(&lease).unwrap();
+    clock.advance(Duration::from_secs(1900));
+    let loaded = store.load_dhcp4().unwrap().unwrap();
+    // stored with one-second resolution
+    let remaining = loaded.times.remaining_valid(clock.now()).unwrap();
+    assert!(remaining <= Duration::from_secs(1700) && remaining > Duration::from_secs(1698), "{:?}", remaining);
+}
+
+#[test]
+fn lease_from_the_future_must_be_rebound() {
+    let clock = Arc::new(FakeClock::new());
+    let store = LeaseStore::new(&temp_dir("lease-future"), "eth0").with_clock(clock.clone());
+    let mut lease = Dhcp4Lease::from_response(&common::dhcp4_ack()).unwrap();
+    lease.times.received_at = ReceivedAt::now(clock.as_ref());
+    store.store_dhcp4(&lease).unwrap();
+    clock.step_wall_back(Duration::from_secs(86400));
+    let loaded = store.load_dhcp4().unwrap().unwrap();
+    assert_eq!(loaded.times.state(clock.now()), LeaseState::Rebinding);
+    assert_eq!(loaded.times.remaining_valid(clock.now()), lease.times.valid);
+}
+
+#[test]
+fn reconfigure_key_is_stored_with_the_lease() {
+    let clock = Arc::new(FakeClock::new());
+    let store = LeaseStore::new(&temp_dir("lease-key"), "eth0").with_clock(clock.clone());
+    let mut res = common::dhcp6_reply(Some(common::pd("2001:db8:1200::/56")));
+    res.reconfigure_key = Some(AuthKey { key: [0x5a; 16], replay_counter: 42 });
+    store.store_dhcp6(&Dhcp6Lease::from_response(&res, 1).unwrap()).unwrap();
+    let loaded = store.load_dhcp6().unwrap().unwrap();
+    assert_eq!(loaded.reconfigure_key, res.reconfigure_key);
+}
+
+#[test]
+fn forcerenew_nonce_is_stored_with_the_lease() {
+    let clock = Arc::new(FakeClock::new());
+    let store = LeaseStore::new(&temp_dir("lease-nonce"), "eth0").with_clock(clock.clone());
+    let mut res = common::dhcp4_ack();
+    res.forcerenew_nonce = Some(AuthKey { key: [0xa5; 16], replay_counter: 9 });
+    store.store_dhcp4(&Dhcp4Lease::from_response(&res).unwrap()).unwrap();
+    let loaded = store.load_dhcp4().unwrap().unwrap();
+    assert_eq!(loaded.forcerenew_nonce, res.forcerenew_nonce);
+}
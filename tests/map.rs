@@ -0,0 +1,138 @@
+mod common;
+
+use std::net::Ipv6Addr;
+
+use ftth_dhcp::map::{select_rule, MapConfig};
+use ftth_dhcp::softwire::{S46PortParams, S46Rule};
+
+fn rule(ipv6_prefix: &str, ipv4_prefix: &str, ea_len: u8, port_params: Option<S46PortParams>) -> S46Rule {
+    S46Rule {
+        fmr: true,
+        ea_len,
+        ipv4_prefix: ipv4_prefix.parse().unwrap(),
+        ipv6_prefix: ipv6_prefix.parse().unwrap(),
+        port_params,
+    }
+}
+
+// RFC 7597 appendix A, example 1
+#[test]
+fn rfc7597_example_1() {
+    let rules = [rule("2001:db8::/40", "192.0.2.0/24", 16, None)];
+    let map = MapConfig::from_pd(&rules, &common::pd("2001:db8:12:3400::/56")).unwrap();
+    assert_eq!(map.ipv4, "192.0.2.18/32".parse().unwrap());
+    assert_eq!(map.psid_len, 8);
+    assert_eq!(map.psid, 0x34);
+    assert_eq!(map.ce_addr, "2001:db8:12:3400:0:c000:212:34".parse::<Ipv6Addr>().unwrap());
+
+    let ports = map.port_ranges();
+    assert_eq!(ports.len(), 63);
+    assert_eq!(ports[0], 1232..=1235);
+    assert_eq!(ports[1], 2256..=2259);
+    assert_eq!(ports[61], 63696..=63699);
+    assert_eq!(ports[62], 64720..=64723);
+}
+
+#[test]
+fn psid_offset_from_port_params() {
+    let params = S46PortParams { offset: 4, psid_len: 8, psid: 0 };
+    let rules = [rule("2001:db8::/40", "192.0.2.0/24", 16, Some(params))];
+    let map = MapConfig::from_pd(&rules, &common::pd("2001:db8:12:3400::/56")).unwrap();
+    let ports = map.port_ranges();
+    assert_eq!(ports.len(), 15);
+    assert_eq!(ports[0], 4928..=4943);
+    assert_eq!(ports.iter().map(|r| r.len()).sum::<usize>(), 15 * 16);
+}
+
+#[test]
+fn ipv4_prefix_without_sharing() {
+    let rules = [rule("2001:db8::/40", "198.51.0.0/16", 8, None)];
+    let map = MapConfig::from_pd(&rules, &common::pd("2001:db8:ab::/48")).unwrap();
+    assert_eq!(map.ipv4, "198.51.171.0/24".parse().unwrap());
+    assert_eq!(map.psid_len, 0);
+    assert_eq!(map.port_ranges(), [0..=u16::MAX]);
+    assert_eq!(map.ce_addr, "2001:db8:ab:0:0:c633:ab00:0".parse::<Ipv6Addr>().unwrap());
+}
+
+#[test]
+fn selects_longest_matching_rule() {
+    let rules = [
+        rule("2001:db8::/32", "192.0.2.0/24", 16, None),
+        rule("2001:db8:12::/48", "203.0.113.0/24", 8, None),
+        rule("2001:db9::/32", "198.51.100.0/24", 16, None),
+    ];
+    let prefix = "2001:db8:12:3400::/56".parse().unwrap();
+    assert_eq!(select_rule(&rules, prefix), Some(&rules[1]));
+    assert!(select_rule(&rules, "2001:db8::/40".parse().unwrap()).is_none());
+    assert!(MapConfig::from_pd(&rules, &common::pd("2001:dba::/56")).is_err());
+}
+
+#[test]
+fn ce_address_keeps_the_whole_delegated_prefix() {
+    // end-user prefix /48, delegated /56
+    let rules = [rule("2001:db8::/40", "198.51.0.0/16", 8, None)];
+    let map = MapConfig::from_pd(&rules, &common::pd("2001:db8:ab:cd00::/56")).unwrap();
+    assert_eq!(map.ipv4, "198.51.171.0/24".parse().unwrap());
+    assert_eq!(map.ce_addr, "2001:db8:ab:cd00:0:c633:ab00:0".parse::<Ipv6Addr>().unwrap());
+    assert!(MapConfig::from_pd(&rules, &common::pd("2001:db8:ab:cd00::/80")).is_err());
+}
+
+#[test]
+fn explicit_psid_without_ea_bits() {
+    // lw4o6-style rule: full IPv4 address, PSID only in S46_PORTPARAMS
+    let params = S46PortParams { offset: 6, psid_len: 8, psid: 0x12 };
+    let rules = [rule("2001:db8::/32", "192.0.2.7/32", 0, Some(params))];
+    let map = MapConfig::from_pd(&rules, &common::pd("2001:db8:ab:cd00::/56")).unwrap();
+    assert_eq!(map.ipv4, "192.0.2.7/32".parse().unwrap());
+    assert_eq!((map.psid, map.psid_len, map.psid_offset), (0x12, 8, 6));
+    assert_eq!(map.ce_addr, "2001:db8:ab:cd00:0:c000:207:12".parse::<Ipv6Addr>().unwrap());
+    assert_eq!(map.port_ranges()[0], 1096..=1099);
+}
+
+// RFC 7597 appendix A, example 2
+#[test]
+fn rfc7597_example_2() {
+    let rule = rule("2001:db8::/40", "192.0.2.0/24", 16, None);
+    let map = MapConfig::for_destination(&rule, "192.0.2.18".parse().unwrap(), 1232).unwrap();
+    assert_eq!(map.psid, 0x34);
+    assert_eq!(map.ce_addr, "2001:db8:12:3400:0:c000:212:34".parse::<Ipv6Addr>().unwrap());
+    assert!(MapConfig::for_destination(&rule, "198.51.100.1".parse().unwrap(), 1232).is_err());
+}
+
+// RFC 7597 appendix A, example 3
+#[test]
+fn rfc7597_example_3() {
+    let rules = [rule("2001:db8::/56", "192.0.2.1/32", 0, None)];
+    let map = MapConfig::from_pd(&rules, &common::pd("2001:db8::/56")).unwrap();
+    assert_eq!(map.ipv4, "192.0.2.1/32".parse().unwrap());
+    assert_eq!(map.psid_len, 0);
+    assert_eq!(map.port_ranges(), [0..=u16::MAX]);
+    assert_eq!(map.ce_addr, "2001:db8::c000:201:0".parse::<Ipv6Addr>().unwrap());
+}
+
+// RFC 7597 appendix A, example 4
+#[test]
+fn rfc7597_example_4() {
+    let params = S46PortParams { offset: 6, psid_len: 8, psid: 0x34 };
+    let rules = [rule("2001:db8::/56", "192.0.2.1/32", 0, Some(params))];
+    let map = MapConfig::from_pd(&rules, &common::pd("2001:db8::/56")).unwrap();
+    assert_eq!(map.ipv4, "192.0.2.1/32".parse().unwrap());
+    assert_eq!((map.psid, map.psid_len), (0x34, 8));
+    assert_eq!(map.ce_addr, "2001:db8::c000:201:34".parse::<Ipv6Addr>().unwrap());
+    let ports = map.port_ranges();
+    assert_eq!(ports[0], 1232..=1235);
+    assert_eq!(ports[1], 2256..=2259);
+    assert_eq!(ports[62], 64720..=64723);
+}
+
+#[test]
+fn explicit_psid_with_ipv4_suffix_in_ea_bits() {
+    // the 8 EA bits only complete the address; the PSID comes from the rule
+    let params = S46PortParams { offset: 4, psid_len: 4, psid: 0x5 };
+    let rules = [rule("2001:db8::/40", "192.0.2.0/24", 8, Some(params))];
+    let map = MapConfig::from_pd(&rules, &common::pd("2001:db8:12::/48")).unwrap();
+    assert_eq!(map.ipv4, "192.0.2.18/32".parse().unwrap());
+    assert_eq!((map.psid, map.psid_len, map.psid_offset), (0x5, 4, 4));
+    assert_eq!(map.ce_addr, "2001:db8:12:0:0:c000:212:5".parse::<Ipv6Addr>().unwrap());
+    assert_eq!(map.port_ranges()[0], 5376..=5631);
+}
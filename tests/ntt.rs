@@ -0,0 +1,150 @@
+mod common;
+
+
+use ftth_dhcp::ntt::{self, NttVendorInfo};
+
+const MAC: [u8; 6] = [0x02, 0x00, 0x5e, 0x10, 0x20, 0x30];
+
+fn sample_info() -> NttVendorInfo {
+    NttVendorInfo {
+        mac_addr: Some(MAC),
+        main_number: Some("0312345678".to_string()),
+        add_numbers: vec!["0312345679".to_string(), "0312345680".to_string()],
+        sip_domain: Some("ntt-east.ne.jp".to_string()),
+    }
+}
+
+#[test]
+fn dhcp4_vendor_info_round_trip() {
+    let info = sample_info();
+    let data = info.encode_dhcp4().unwrap();
+    assert_eq!(&data[0..4], &210u32.to_be_bytes());
+    assert_eq!(data[4] as usize, data.len() - 5);
+    assert_eq!(NttVendorInfo::decode_dhcp4(&data).unwrap(), info);
+}
+
+#[test]
+fn dhcp6_vendor_info_round_trip() {
+    let info = sample_info();
+    let data = info.encode_dhcp6().unwrap();
+    assert_eq!(&data[0..4], &210u32.to_be_bytes());
+    assert_eq!(NttVendorInfo::decode_dhcp6(&data).unwrap(), info);
+}
+
+#[test]
+fn dhcp4_vendor_info_known_bytes() {
+    let data = [
+        0, 0, 0, 210, 16,
+        201, 6, 0x02, 0x00, 0x5e, 0x10, 0x20, 0x30,
+        204, 6, 3, b'n', b'g', b'n', 0, 0,
+    ];
+    let info = NttVendorInfo::decode_dhcp4(&data).unwrap();
+    assert_eq!(info.mac_addr, Some(MAC));
+    assert_eq!(info.sip_domain.as_deref(), Some("ngn"));
+}
+
+#[test]
+fn dhcp4_vendor_info_tolerates_bad_length() {
+    let info = sample_info();
+    let mut data = info.encode_dhcp4().unwrap();
+    data[4] += 1;
+    assert_eq!(NttVendorInfo::decode_dhcp4(&data).unwrap(), info);
+
+    // trailing bytes past data-len are ignored
+    let mut data = info.encode_dhcp4().unwrap();
+    data.extend_from_slice(&[0xff, 0xff]);
+    assert_eq!(NttVendorInfo::decode_dhcp4(&data).unwrap(), info);
+}
+
+#[test]
+fn dhcp4_vendor_info_among_other_enterprises() {
+    let mut data = vec![0, 0, 0x01, 0x37, 3, 1, 1, 0];
+    data.extend_from_slice(&sample_info().encode_dhcp4().unwrap());
+    assert_eq!(NttVendorInfo::decode_dhcp4(&data).unwrap(), sample_info());
+    assert!(NttVendorInfo::decode_dhcp4(&data[..8]).is_err());
+}
+
+#[test]
+fn oversized_vendor_info_is_not_truncated() {
+    let info = NttVendorInfo {
+        add_numbers: vec!["0".repeat(256)],
+        ..Default::default()
+    };
+    assert!(info.encode_dhcp4().is_err());
+    assert!(info.encode_dhcp6().is_ok());
+
+    // each suboption fits, the whole does not
+    let info = NttVendorInfo {
+        add_numbers: vec!["0".repeat(200), "1".repeat(200)],
+        ..Default::default()
+    };
+    assert!(info.encode_dhcp4().is_err());
+
+    let info = NttVendorInfo {
+        sip_domain: Some(format!("{}.jp", "a".repeat(64))),
+        ..Default::default()
+    };
+    assert!(info.encode_dhcp4().is_err());
+}
+
+#[test]
+fn vendor_info_from_suboptions() {
+    let mac = MAC.to_vec();
+    let info = NttVendorInfo::from_suboptions([(ntt::SUBOPT_MAC_ADDR, &mac[..]), (ntt::SUBOPT_MAIN_NUMBER, b"0312345678")]).unwrap();
+    assert_eq!(info.mac_addr, Some(MAC));
+    assert_eq!(info.main_number.as_deref(), Some("0312345678"));
+    assert!(NttVendorInfo::from_suboptions([(ntt::SUBOPT_MAC_ADDR, &mac[..5])]).is_err());
+}
+
+#[test]
+fn vendor_info_rejects_other_enterprise() {
+    let mut data = sample_info().encode_dhcp6().unwrap();
+    data[3] = 211;
+    assert!(NttVendorInfo::decode_dhcp6(&data).is_err());
+}
+
+#[test]
+fn verify_mac() {
+    let info = sample_info();
+    assert!(info.verify_mac(MAC).is_ok());
+    assert!(info.verify_mac([0; 6]).is_err());
+    assert!(NttVendorInfo::default().verify_mac([0; 6]).is_ok());
+}
+
+#[test]
+fn vendor_class_round_trip() {
+    let data = ntt::encode_dhcp4_vendor_class(MAC);
+    assert_eq!(data, [0, 0, 0, 210, 7, 6, 0x02, 0x00, 0x5e, 0x10, 0x20, 0x30]);
+    assert_eq!(ntt::decode_dhcp4_vendor_class(&data).unwrap(), MAC);
+
+    let data = ntt::encode_dhcp6_vendor_class(MAC);
+    assert_eq!(data, [0, 0, 0, 210, 0, 6, 0x02, 0x00, 0x5e, 0x10, 0x20, 0x30]);
+    assert_eq!(ntt::decode_dhcp6_vendor_class(&data).unwrap(), MAC);
+}
+
+#[test]
+fn ack_exposes_the_vendor_info() {
+    use dhcproto::v4::{DhcpOption, MessageType, OptionCode, UnknownOption};
+    use ftth_dhcp::ipv4::{Dhcp4Client, Dhcp4ClientConfig, Dhcp4Outcome};
+
+    let server = common::FakeServer::new(|packet| {
+        let msg_type = match common::decode_dhcp4(packet).opts().msg_type() {
+            Some(MessageType::Discover) => MessageType::Offer,
+            _ => MessageType::Ack,
+        };
+        // another enterprise first, as RFC 3925 allows
+        let mut data = vec![0, 0, 0x01, 0x37, 2, 1, 0];
+        data.extend_from_slice(&sample_info().encode_dhcp4().unwrap());
+        let opt = DhcpOption::Unknown(UnknownOption::new(OptionCode::Unknown(125), data));
+        vec![common::dhcp4_answer(packet, msg_type, vec![opt])]
+    });
+    let config = Dhcp4ClientConfig::default().with_clock(server.clock.clone());
+    let client = Dhcp4Client::with_transport(MAC, config, server.clone());
+    let Dhcp4Outcome::Lease(ack) = client.obtain_lease(None).unwrap() else {
+        panic!("no lease");
+    };
+    assert_eq!(ack.ntt_vendor_info, Some(sample_info()));
+    assert_eq!(ack.sip_main_number.as_deref(), Some("0312345678"));
+    assert_eq!(ack.sip_domain_name.as_deref(), Some("ntt-east.ne.jp"));
+    assert_eq!(ack.ntt_mac_mismatch, None);
+}
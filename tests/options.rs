@@ -0,0 +1,101 @@
+use std::net::Ipv4Addr;
+
+use dhcproto::{v4, v6, Encodable, Encoder};
+use ftth_dhcp::options::{parse_dhcp4_options, parse_dhcp6_options, OptionDecoders, OptionKey, RawOption};
+
+fn raw(code: u16, data: &[u8]) -> RawOption {
+    RawOption { code, data: data.to_vec() }
+}
+
+fn dhcp4_packet(options: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0u8; 236];
+    packet.extend_from_slice(&[99, 130, 83, 99]);
+    packet.extend_from_slice(options);
+    packet
+}
+
+#[test]
+fn dhcp4_options_round_trip() {
+    let mut msg = v4::Message::default();
+    msg.opts_mut().insert(v4::DhcpOption::MessageType(v4::MessageType::Ack));
+    msg.opts_mut().insert(v4::DhcpOption::SubnetMask(Ipv4Addr::new(255, 255, 255, 0)));
+    msg.opts_mut().insert(v4::DhcpOption::Unknown(v4::UnknownOption::new(v4::OptionCode::Unknown(224), vec![1, 2, 3])));
+    let mut buf = Vec::new();
+    msg.encode(&mut Encoder::new(&mut buf)).unwrap();
+
+    let options = parse_dhcp4_options(&buf);
+    assert!(options.contains(&raw(53, &[5])));
+    assert!(options.contains(&raw(1, &[255, 255, 255, 0])));
+    assert!(options.contains(&raw(224, &[1, 2, 3])));
+}
+
+#[test]
+fn dhcp4_split_options_are_concatenated() {
+    let packet = dhcp4_packet(&[224, 2, 1, 2, 0, 224, 1, 3, 255, 224, 1, 4]);
+    assert_eq!(parse_dhcp4_options(&packet), [raw(224, &[1, 2, 3])]);
+}
+
+#[test]
+fn dhcp4_truncated_options() {
+    assert_eq!(parse_dhcp4_options(&[0u8; 100]), []);
+    assert_eq!(parse_dhcp4_options(&dhcp4_packet(&[1, 4, 255, 255])), []);
+    assert_eq!(parse_dhcp4_options(&dhcp4_packet(&[53, 1, 5, 1])), [raw(53, &[5])]);
+}
+
+#[test]
+fn dhcp6_options_round_trip() {
+    let mut msg = v6::Message::new(v6::MessageType::Reply);
+    msg.opts_mut().insert(v6::DhcpOption::ClientId(vec![0, 3, 0, 1, 2, 0, 0, 0, 0, 1]));
+    msg.opts_mut().insert(v6::DhcpOption::Unknown(v6::UnknownOption::new(v6::OptionCode::Unknown(65000), vec![9, 8])));
+    let mut buf = Vec::new();
+    msg.encode(&mut Encoder::new(&mut buf)).unwrap();
+
+    let options = parse_dhcp6_options(&buf[4..]);
+    assert!(options.contains(&raw(1, &[0, 3, 0, 1, 2, 0, 0, 0, 0, 1])));
+    assert!(options.contains(&raw(65000, &[9, 8])));
+}
+
+#[test]
+fn dhcp6_truncated_options() {
+    assert_eq!(parse_dhcp6_options(&[0, 1, 0]), []);
+    assert_eq!(parse_dhcp6_options(&[0, 14, 0, 0, 0, 1, 0, 4, 1, 2]), [raw(14, &[])]);
+}
+
+#[test]
+fn vendor_suboptions_of_several_enterprises() {
+    let mut decoders = OptionDecoders::new();
+    decoders.register(OptionKey::Vendor { enterprise: 210, code: 202 }, |data| Ok(data.to_vec()));
+    decoders.register(OptionKey::Vendor { enterprise: 4491, code: 1 }, |data| Ok(data.to_vec()));
+    let data = [
+        0, 0, 0, 210, 4, 202, 2, b'0', b'3',
+        0, 0, 0x11, 0x8b, 3, 1, 1, 7,
+    ];
+    let custom = decoders.decode_dhcp4(&[raw(125, &data)]);
+    assert_eq!(custom.get::<Vec<u8>>(OptionKey::Vendor { enterprise: 210, code: 202 }), Some(&b"03".to_vec()));
+    assert_eq!(custom.get::<Vec<u8>>(OptionKey::Vendor { enterprise: 4491, code: 1 }), Some(&vec![7]));
+
+    // a suboption running past its enterprise block is dropped
+    let custom = decoders.decode_dhcp4(&[raw(125, &[0, 0, 0, 210, 3, 202, 2, b'0', b'3'])]);
+    assert!(custom.is_empty());
+}
+
+#[test]
+fn dhcp6_vendor_suboptions() {
+    let mut decoders = OptionDecoders::new();
+    decoders.register(OptionKey::Vendor { enterprise: 210, code: 204 }, |data| Ok(data.len()));
+    decoders.register(OptionKey::Option(17), |data| Ok(data.len()));
+    let data = [0, 0, 0, 210, 0, 204, 0, 2, 1, 2, 0, 205, 0, 9];
+    let custom = decoders.decode_dhcp6(&[raw(17, &data)]);
+    assert_eq!(custom.get::<usize>(OptionKey::Vendor { enterprise: 210, code: 204 }), Some(&2));
+    assert_eq!(custom.get::<usize>(OptionKey::Option(17)), Some(&data.len()));
+    assert_eq!(custom.get_all(OptionKey::Vendor { enterprise: 210, code: 205 }).len(), 0);
+}
+
+#[test]
+fn failing_decoder_is_skipped() {
+    let mut decoders = OptionDecoders::new();
+    decoders.register(OptionKey::Option(224), |_| -> std::io::Result<u8> {
+        Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "bad"))
+    });
+    assert!(decoders.decode_dhcp4(&[raw(224, &[1])]).is_empty());
+}
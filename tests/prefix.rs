@@ -0,0 +1,139 @@
+mod common;
+
+use ftth_dhcp::ipv6::decode_pd_exclude;
+use ftth_dhcp::prefix_plan::{LanRequest, PrefixPlanner};
+use ipnet::Ipv6Net;
+
+fn net(s: &str) -> Ipv6Net {
+    s.parse().unwrap()
+}
+
+#[test]
+fn decode_pd_exclude_subnet_id() {
+    let delegated = net("2001:db8:0:ff00::/56");
+    assert_eq!(decode_pd_exclude(delegated, &[64, 0x01]).unwrap(), net("2001:db8:0:ff01::/64"));
+    // 10 bits of subnet ID, left-aligned in two bytes
+    let delegated = net("2001:db8::/48");
+    assert_eq!(decode_pd_exclude(delegated, &[58, 0xff, 0xc0]).unwrap(), net("2001:db8:0:ffc0::/58"));
+
+    assert!(decode_pd_exclude(delegated, &[48]).is_err());
+    assert!(decode_pd_exclude(delegated, &[64, 0x01]).is_err());
+}
+
+#[test]
+fn lan_subnets_skip_excluded() {
+    let delegated = "2001:db8:0:ff00::/56";
+    let subnets: Vec<_> = common::pd(delegated).lan_subnets().collect();
+    assert_eq!(subnets.len(), 256);
+    assert_eq!(subnets[0], net("2001:db8:0:ff00::/64"));
+
+    let subnets: Vec<_> = common::pd_excluding(delegated, "2001:db8:0:ff01::/64").lan_subnets().collect();
+    assert_eq!(subnets.len(), 255);
+    assert!(!subnets.contains(&net("2001:db8:0:ff01::/64")));
+
+    // a longer exclusion still takes its whole /64
+    let subnets = common::pd_excluding(delegated, "2001:db8:0:ff02::/127").lan_subnets().count();
+    assert_eq!(subnets, 255);
+
+    assert_eq!(common::pd("2001:db8::1/128").lan_subnets().count(), 0);
+}
+
+#[test]
+fn plan_keeps_subnet_ids_across_renumbering() {
+    let lans = vec![
+        LanRequest::new("br-lan", 64),
+        LanRequest::new("guest", 64).with_hint(0x10),
+        LanRequest::new("downstream", 60),
+    ];
+    let mut planner = PrefixPlanner::new(lans.clone());
+    let plan = planner.plan(&common::pd_excluding("2001:db8:0:ff00::/56", "2001:db8:0:ff00::/64"));
+    assert!(plan.unassigned.is_empty());
+    assert_eq!(plan.get("guest").unwrap().prefix, net("2001:db8:0:ff10::/64"));
+    assert_eq!(plan.get("downstream").unwrap().prefix, net("2001:db8:0:ff20::/60"));
+    assert_eq!(plan.get("br-lan").unwrap().prefix, net("2001:db8:0:ff01::/64"));
+    assert_eq!(plan.assignments.iter().map(|a| a.name.as_str()).collect::<Vec<_>>(), ["br-lan", "guest", "downstream"]);
+
+    // the same delegation again is not a change
+    assert_eq!(planner.update(&common::pd_excluding("2001:db8:0:ff00::/56", "2001:db8:0:ff00::/64")), None);
+
+    // after a restart, a new delegation keeps the subnet IDs
+    let mut restarted = PrefixPlanner::new(lans);
+    restarted.decode(&planner.encode()).unwrap();
+    let plan = restarted.update(&common::pd("2001:db8:1:ab00::/56")).unwrap();
+    assert_eq!(plan.get("br-lan").unwrap().prefix, net("2001:db8:1:ab01::/64"));
+    assert_eq!(plan.get("downstream").unwrap().prefix, net("2001:db8:1:ab20::/60"));
+}
+
+#[test]
+fn plan_reports_lans_that_do_not_fit() {
+    let lans = vec![
+        LanRequest::new("a", 63),
+        LanRequest::new("b", 64),
+        LanRequest::new("c", 64),
+        LanRequest::new("too-long", 80),
+    ];
+    let plan = PrefixPlanner::new(lans).plan(&common::pd("2001:db8:0:ff00::/62"));
+    assert_eq!(plan.get("a").unwrap().prefix, net("2001:db8:0:ff00::/63"));
+    assert_eq!(plan.get("b").unwrap().prefix, net("2001:db8:0:ff02::/64"));
+    assert_eq!(plan.get("c").unwrap().prefix, net("2001:db8:0:ff03::/64"));
+    assert_eq!(plan.unassigned, ["too-long"]);
+}
+
+#[test]
+fn plan_skips_taken_prefixes_in_short_delegations() {
+    // 2^32 /64s; the planner must not walk them one by one
+    let mut lans = vec![LanRequest::new("wide", 33)];
+    lans.extend((0..4).map(|i| LanRequest::new(&format!("lan{i}"), 64)));
+    let plan = PrefixPlanner::new(lans).plan(&common::pd_excluding("2001:db8::/32", "2001:db8::/64"));
+    assert!(plan.unassigned.is_empty());
+    assert_eq!(plan.get("wide").unwrap().prefix, net("2001:db8:8000::/33"));
+    assert_eq!(plan.get("lan0").unwrap().prefix, net("2001:db8:0:1::/64"));
+    assert_eq!(plan.get("lan3").unwrap().prefix, net("2001:db8:0:4::/64"));
+
+    // nothing left: the LAN is reported instead of scanning forever
+    let lans = vec![LanRequest::new("all", 32), LanRequest::new("late", 64)];
+    let plan = PrefixPlanner::new(lans).plan(&common::pd("2001:db8::/32"));
+    assert_eq!(plan.unassigned, ["late"]);
+}
+
+/// The body of the first top-level option `code` in a DHCPv6 message.
+fn dhcp6_option(packet: &[u8], code: u16) -> &[u8] {
+    let mut pos = 4;
+    while pos + 4 <= packet.len() {
+        let len = u16::from_be_bytes([packet[pos + 2], packet[pos + 3]]) as usize;
+        if u16::from_be_bytes([packet[pos], packet[pos + 1]]) == code {
+            return &packet[pos + 4..pos + 4 + len];
+        }
+        pos += 4 + len;
+    }
+    panic!("no option {}", code);
+}
+
+#[test]
+fn solicit_carries_pd_hint() {
+    use std::time::Duration;
+
+    use ftth_dhcp::ipv6::{Dhcp6Client, Dhcp6ClientConfig, PdHint};
+
+    let server = common::FakeServer::silent();
+    let config = Dhcp6ClientConfig::default().with_clock(server.clock.clone());
+    let client = Dhcp6Client::with_transport("fe80::1".parse().unwrap(), common::MAC, config, server.clone()).unwrap();
+    let iapd_header = [0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 26, 0, 25, 0, 0, 0, 0, 0, 0, 0, 0];
+
+    // only the length: the prefix is all zeros (RFC 8415 section 18.2.1)
+    client.solicit_pd_with_hint(Duration::ZERO, 7, Some(PdHint::length(56))).unwrap();
+    let mut expected = iapd_header.to_vec();
+    expected.push(56);
+    expected.extend_from_slice(&[0; 16]);
+    assert_eq!(dhcp6_option(&server.sent()[0].packet, 25), expected);
+
+    client.solicit_pd_with_hint(Duration::ZERO, 7, Some(PdHint::prefix("2001:db8:0:ff00::".parse().unwrap(), 56))).unwrap();
+    let mut expected = iapd_header.to_vec();
+    expected.push(56);
+    expected.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(dhcp6_option(&server.sent()[1].packet, 25), expected);
+
+    // no hint: an empty IA_PD
+    client.solicit_pd_with_hint(Duration::ZERO, 7, None).unwrap();
+    assert_eq!(dhcp6_option(&server.sent()[2].packet, 25), [0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0, 0]);
+}
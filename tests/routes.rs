@@ -0,0 +1,81 @@
+mod common;
+
+use ftth_dhcp::ipv4::{parse_classless_routes, Dhcp4Route};
+
+fn route(addr: [u8; 4], prefix_len: u8, gateway: [u8; 4]) -> Dhcp4Route {
+    Dhcp4Route {
+        prefix_addr: addr.into(),
+        prefix_len,
+        gateway: gateway.into(),
+    }
+}
+
+#[test]
+fn classless_routes_are_parsed() {
+    let data = [
+        0, 192, 0, 2, 1,
+        8, 10, 192, 0, 2, 2,
+        20, 172, 16, 16, 192, 0, 2, 3,
+        32, 198, 51, 100, 7, 192, 0, 2, 4,
+    ];
+    assert_eq!(parse_classless_routes(&data), [
+        route([0, 0, 0, 0], 0, [192, 0, 2, 1]),
+        route([10, 0, 0, 0], 8, [192, 0, 2, 2]),
+        route([172, 16, 16, 0], 20, [192, 0, 2, 3]),
+        route([198, 51, 100, 7], 32, [192, 0, 2, 4]),
+    ]);
+}
+
+#[test]
+fn classless_routes_stop_at_malformed_entry() {
+    // truncated gateway
+    assert_eq!(parse_classless_routes(&[8, 10, 192, 0, 2]), []);
+    // prefix length above 32
+    assert_eq!(parse_classless_routes(&[8, 10, 192, 0, 2, 2, 33, 1, 2, 3, 4, 5, 192, 0, 2, 1]), [
+        route([10, 0, 0, 0], 8, [192, 0, 2, 2]),
+    ]);
+}
+
+#[test]
+fn router_and_classful_routes_without_option_121() {
+    let mut res = common::dhcp4_ack();
+    res.classful_routes = vec![route([10, 0, 0, 0], 8, [192, 0, 2, 5]), route([0, 0, 0, 0], 8, [192, 0, 2, 6])];
+    let set = res.effective_routes();
+    assert_eq!(set.connected, Some("192.0.2.0/24".parse().unwrap()));
+    assert_eq!(set.routes, [route([0, 0, 0, 0], 0, [192, 0, 2, 1]), route([10, 0, 0, 0], 8, [192, 0, 2, 5])]);
+}
+
+#[test]
+fn option_121_overrides_router_and_option_33() {
+    let mut res = common::dhcp4_ack();
+    res.classful_routes = vec![route([10, 0, 0, 0], 8, [192, 0, 2, 5])];
+    res.ms_static_routes = vec![route([172, 16, 0, 0], 12, [192, 0, 2, 7])];
+    res.static_routes = vec![route([198, 51, 100, 0], 24, [192, 0, 2, 8])];
+    assert_eq!(res.effective_routes().routes, [route([198, 51, 100, 0], 24, [192, 0, 2, 8])]);
+
+    res.static_routes.clear();
+    assert_eq!(res.effective_routes().routes, [route([172, 16, 0, 0], 12, [192, 0, 2, 7])]);
+}
+
+#[test]
+fn invalid_prefix_length_is_skipped() {
+    let bad = route([10, 0, 0, 0], 40, [192, 0, 2, 9]);
+    assert_eq!(bad.prefix(), None);
+    let mut res = common::dhcp4_ack();
+    res.static_routes = vec![bad, route([10, 0, 0, 0], 8, [192, 0, 2, 2])];
+    assert_eq!(res.effective_routes().routes, [route([10, 0, 0, 0], 8, [192, 0, 2, 2])]);
+}
+
+#[test]
+fn profiles_request_every_route_option() {
+    use dhcproto::v4::OptionCode;
+    use ftth_dhcp::profile::{GenericProfile, NttNgnProfile, ProvisioningProfile};
+
+    let profiles: [&dyn ProvisioningProfile; 2] = [&GenericProfile, &NttNgnProfile];
+    for profile in profiles {
+        let params = profile.dhcp4_request_params();
+        for code in [OptionCode::ClasslessStaticRoute, OptionCode::StaticRoutingTable, OptionCode::Unknown(249)] {
+            assert!(params.contains(&code), "{} does not request {code:?}", profile.name());
+        }
+    }
+}
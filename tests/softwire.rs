@@ -0,0 +1,55 @@
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use ftth_dhcp::softwire::{decode_aftr_name, S46Container, S46PortParams};
+
+fn opt(code: u16, data: &[u8]) -> Vec<u8> {
+    let mut buf = code.to_be_bytes().to_vec();
+    buf.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    buf.extend_from_slice(data);
+    buf
+}
+
+#[test]
+fn decode_aftr_name_fqdn() {
+    let data = b"\x04gw\x2dB\x07transix\x02jp\x00";
+    assert_eq!(decode_aftr_name(data).unwrap(), "gw-b.transix.jp");
+    assert!(decode_aftr_name(b"\x07transix").is_err());
+}
+
+#[test]
+fn decode_map_e_container() {
+    // FMR 240b:10::/31 <-> 106.72.0.0/15, 25 EA bits, a = 4
+    let mut rule = vec![0x01, 25, 15, 106, 72, 0, 0, 31, 0x24, 0x0b, 0x00, 0x10];
+    rule.extend(opt(93, &[4, 0, 0, 0]));
+    let mut data = opt(89, &rule);
+    let br: Ipv6Addr = "2404:9200:225:100::64".parse().unwrap();
+    data.extend(opt(90, &br.octets()));
+    data.extend(opt(9999, b"ignored"));
+
+    let c = S46Container::decode(&data).unwrap();
+    assert_eq!(c.br_addrs, [br]);
+    assert_eq!(c.rules.len(), 1);
+    let rule = &c.rules[0];
+    assert!(rule.fmr);
+    assert_eq!(rule.ea_len, 25);
+    assert_eq!(rule.ipv4_prefix, "106.72.0.0/15".parse().unwrap());
+    assert_eq!(rule.ipv6_prefix, "240b:10::/31".parse().unwrap());
+    assert_eq!(rule.port_params, Some(S46PortParams { offset: 4, psid_len: 0, psid: 0 }));
+}
+
+#[test]
+fn decode_lw4o6_container() {
+    let mut bind = vec![192, 0, 2, 1, 64, 0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 1];
+    // PSID 0x34 in the top 8 bits
+    bind.extend(opt(93, &[6, 8, 0x34, 0x00]));
+    let data = opt(92, &bind);
+
+    let c = S46Container::decode(&data).unwrap();
+    let bind = c.v4v6bind.unwrap();
+    assert_eq!(bind.ipv4_addr, Ipv4Addr::new(192, 0, 2, 1));
+    assert_eq!(bind.bind_prefix, "2001:db8:0:1::/64".parse().unwrap());
+    assert_eq!(bind.port_params, Some(S46PortParams { offset: 6, psid_len: 8, psid: 0x34 }));
+
+    assert!(S46Container::decode(&data[..data.len() - 1]).is_err());
+}
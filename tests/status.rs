@@ -0,0 +1,14 @@
+
+use std::io::{Error, ErrorKind};
+
+use dhcproto::v6::{Status, StatusCode};
+use ftth_dhcp::ipv6::StatusError;
+
+#[test]
+fn status_is_found_in_the_error() {
+    let code = StatusCode { status: Status::UnspecFail, msg: "try later".to_string() };
+    let e = Error::new(ErrorKind::ConnectionAborted, StatusError(code.clone()));
+    assert_eq!(StatusError::of(&e), Some(&StatusError(code)));
+    assert!(e.to_string().contains("try later"));
+    assert_eq!(StatusError::of(&Error::new(ErrorKind::ConnectionAborted, "DHCPNAK")), None);
+}
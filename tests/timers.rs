@@ -0,0 +1,218 @@
+
+mod common;
+
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+
+use ftth_dhcp::clock::{random_factor, retransmit, Clock, ClockJump, FakeClock, JumpDetector, RetransParams, Retransmission};
+use ftth_dhcp::ipv4::{Dhcp4Client, Dhcp4ClientConfig};
+use ftth_dhcp::lease::{LeaseState, LeaseTimes, ReceivedAt, INFINITE_LIFETIME};
+
+fn secs(s: u64) -> Duration {
+    Duration::from_secs(s)
+}
+
+#[test]
+fn solicit_backoff_doubles_up_to_mrt() {
+    let clock = FakeClock::new();
+    let mut retrans = Retransmission::new(RetransParams::SOLICIT, clock.now());
+    let mut timeouts = Vec::new();
+    for _ in 0..14 {
+        let rt = retrans.next_timeout(clock.now(), 0.0).unwrap();
+        clock.sleep(rt);
+        timeouts.push(rt.as_secs());
+    }
+    assert_eq!(timeouts, [1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 1024, 2048, 3600, 3600]);
+}
+
+#[test]
+fn first_solicit_timeout_exceeds_irt() {
+    let clock = FakeClock::new();
+    let mut retrans = Retransmission::new(RetransParams::SOLICIT, clock.now());
+    assert!(retrans.next_timeout(clock.now(), -0.1).unwrap() > secs(1));
+    let mut retrans = Retransmission::new(RetransParams::REQUEST, clock.now());
+    assert!(retrans.next_timeout(clock.now(), -0.1).unwrap() < secs(1));
+}
+
+#[test]
+fn request_gives_up_after_mrc() {
+    let clock = FakeClock::new();
+    let mut retrans = Retransmission::new(RetransParams::REQUEST, clock.now());
+    while let Some(rt) = retrans.next_timeout(clock.now(), 0.0) {
+        clock.sleep(rt);
+    }
+    assert_eq!(retrans.count(), 10);
+}
+
+#[test]
+fn confirm_gives_up_after_mrd() {
+    let clock = FakeClock::new();
+    let start = clock.now();
+    let mut retrans = Retransmission::new(RetransParams::CONFIRM, start);
+    while let Some(rt) = retrans.next_timeout(clock.now(), 0.0) {
+        clock.sleep(rt);
+    }
+    assert_eq!(clock.now() - start, secs(10));
+}
+
+#[test]
+fn stray_packets_do_not_end_the_exchange() {
+    let clock = FakeClock::new();
+    let mut sent = 0;
+    let mut replies = [Err(ErrorKind::InvalidData), Err(ErrorKind::InvalidData), Ok(7)].into_iter();
+    let res = retransmit(&clock, RetransParams::DHCP4, |_| {
+        sent += 1;
+        Ok(())
+    }, |_| {
+        clock.advance(secs(1));
+        replies.next().unwrap().map_err(|kind| Error::new(kind, "stray"))
+    });
+    assert_eq!(res.unwrap(), 7);
+    assert_eq!(sent, 1);
+}
+
+#[test]
+fn stray_packets_do_not_extend_the_timeout() {
+    let clock = FakeClock::new();
+    let mut elapsed = Vec::new();
+    let res: std::io::Result<()> = retransmit(&clock, RetransParams::DHCP4, |e| {
+        elapsed.push(e);
+        Ok(())
+    }, |left| {
+        clock.advance(left.min(secs(3)));
+        Err(Error::new(ErrorKind::InvalidData, "stray"))
+    });
+    assert_eq!(res.unwrap_err().kind(), ErrorKind::TimedOut);
+    assert_eq!(elapsed.len(), 5);
+    assert!(elapsed.windows(2).all(|w| w[1] > w[0]));
+    assert!(elapsed[1] >= secs(3) && elapsed[1] <= secs(5));
+}
+
+#[test]
+fn other_errors_end_the_exchange() {
+    let clock = FakeClock::new();
+    let res: std::io::Result<()> = retransmit(&clock, RetransParams::REQUEST, |_| Ok(()), |_| {
+        Err(Error::new(ErrorKind::ConnectionAborted, "status"))
+    });
+    assert_eq!(res.unwrap_err().kind(), ErrorKind::ConnectionAborted);
+}
+
+#[test]
+fn lease_defaults_t1_t2() {
+    let clock = FakeClock::new();
+    let times = LeaseTimes::new(ReceivedAt::now(&clock), 3600, 3600, 0, 0);
+    assert_eq!(times.t1, Some(secs(1800)));
+    assert_eq!(times.t2, Some(secs(3150)));
+
+    // T1 > T2 is ignored
+    let times = LeaseTimes::new(ReceivedAt::now(&clock), 3600, 3600, 3000, 2000);
+    assert_eq!(times.t1, Some(secs(1800)));
+}
+
+#[test]
+fn lease_state_follows_clock() {
+    let clock = FakeClock::new();
+    let times = LeaseTimes::new(ReceivedAt::now(&clock), 3600, 7200, 1000, 2000);
+    assert_eq!(times.state(clock.now()), LeaseState::Bound);
+    assert_eq!(times.next_deadline(clock.now()), times.renew_at());
+
+    clock.advance(secs(1000));
+    assert_eq!(times.state(clock.now()), LeaseState::Renewing);
+    clock.advance(secs(1000));
+    assert_eq!(times.state(clock.now()), LeaseState::Rebinding);
+    assert_eq!(times.remaining_preferred(clock.now()), Some(secs(1600)));
+    clock.advance(secs(5200));
+    assert_eq!(times.state(clock.now()), LeaseState::Expired);
+    assert_eq!(times.next_deadline(clock.now()), None);
+}
+
+#[test]
+fn infinite_lease_never_expires() {
+    let clock = FakeClock::new();
+    let times = LeaseTimes::new(ReceivedAt::now(&clock), INFINITE_LIFETIME, INFINITE_LIFETIME, 0, 0);
+    assert_eq!(times.t1, None);
+    assert_eq!(times.expires_at(), None);
+    clock.advance(secs(100 * 365 * 86400));
+    assert_eq!(times.state(clock.now()), LeaseState::Bound);
+    assert_eq!(times.remaining_valid(clock.now()), None);
+}
+
+#[test]
+fn wait_returns_at_each_deadline() {
+    let clock = FakeClock::new();
+    let start = clock.now();
+    let mut times = LeaseTimes::new(ReceivedAt::now(&clock), 3600, 3600, 1000, 2000);
+    assert_eq!(times.wait(&clock), LeaseState::Renewing);
+    assert_eq!(clock.now() - start, secs(1000));
+    assert_eq!(times.wait(&clock), LeaseState::Rebinding);
+    assert_eq!(times.wait(&clock), LeaseState::Expired);
+    assert_eq!(clock.now() - start, secs(3600));
+}
+
+#[test]
+fn deadlines_count_time_suspended() {
+    let clock = FakeClock::new();
+    let times = LeaseTimes::new(ReceivedAt::now(&clock), 3600, 3600, 1000, 2000);
+    let mut detector = JumpDetector::new(&clock, JumpDetector::DEFAULT_THRESHOLD);
+    // suspended for an hour during a one-minute sleep
+    clock.advance(secs(3660));
+    assert_eq!(detector.check(&clock, secs(60)), Some(ClockJump::Suspended(secs(3600))));
+    assert_eq!(times.state(clock.now()), LeaseState::Expired);
+}
+
+#[test]
+fn wall_clock_steps_do_not_move_deadlines() {
+    let clock = FakeClock::new();
+    let times = LeaseTimes::new(ReceivedAt::now(&clock), 3600, 3600, 1000, 2000);
+    let mut detector = JumpDetector::new(&clock, JumpDetector::DEFAULT_THRESHOLD);
+    clock.advance(secs(60));
+    clock.step_wall_forward(secs(86400));
+    assert_eq!(detector.check(&clock, secs(60)), Some(ClockJump::WallStepped { forward: true, by: secs(86400) }));
+    clock.advance(secs(60));
+    assert_eq!(detector.check(&clock, secs(60)), None);
+    assert_eq!(times.state(clock.now()), LeaseState::Bound);
+}
+
+#[test]
+fn small_wall_steps_are_ignored_both_ways() {
+    let clock = FakeClock::new();
+    let mut detector = JumpDetector::new(&clock, JumpDetector::DEFAULT_THRESHOLD);
+    clock.advance(secs(60));
+    clock.step_wall_back(secs(2));
+    assert_eq!(detector.check(&clock, secs(60)), None);
+    clock.advance(secs(60));
+    clock.step_wall_forward(secs(2));
+    assert_eq!(detector.check(&clock, secs(60)), None);
+    clock.step_wall_back(secs(30));
+    assert_eq!(detector.check(&clock, Duration::ZERO), Some(ClockJump::WallStepped { forward: false, by: secs(30) }));
+}
+
+#[test]
+fn client_retransmits_on_the_injected_clock() {
+    let server = common::FakeServer::silent();
+    let config = Dhcp4ClientConfig::default().with_clock(server.clock.clone());
+    let client = Dhcp4Client::with_transport(common::MAC, config, server.clone());
+    let err = client.obtain_lease(None).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+
+    // five DHCPDISCOVERs, 4, 8, 16 and 32 seconds apart, give or take the
+    // randomization, without any real time passing
+    let sent = server.sent();
+    assert_eq!(sent.len(), 5);
+    let msgs: Vec<_> = sent.iter().map(|s| common::decode_dhcp4(&s.packet)).collect();
+    assert!(msgs.iter().all(|m| m.xid() == msgs[0].xid()));
+    for (i, pair) in sent.windows(2).enumerate() {
+        let gap = (pair[1].at - pair[0].at).as_secs_f64();
+        let expected = 4.0 * f64::from(1u32 << i);
+        assert!(gap > expected * 0.7 && gap < expected * 1.3, "gap {} is {}s", i, gap);
+        assert_eq!(u64::from(msgs[i + 1].secs()), pair[1].at.as_secs());
+    }
+    assert!(server.clock.now().since_boot() < secs(124 * 13 / 10));
+}
+
+#[test]
+fn random_factor_stays_in_range_and_varies() {
+    let factors: Vec<f64> = (0..1000).map(|_| random_factor()).collect();
+    assert!(factors.iter().all(|f| (-0.1..=0.1).contains(f)));
+    assert!(factors.iter().any(|f| *f < -0.05) && factors.iter().any(|f| *f > 0.05));
+}